@@ -0,0 +1,137 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Backups are named `{target}.pono-{timestamp}.bak` so pono only ever touches
+// the backups it made itself.
+const PREFIX: &str = ".pono-";
+const SUFFIX: &str = ".bak";
+// Targets replaced with `on_conflict = "overwrite"` are moved aside until the
+// links are in place, then removed
+const TRASH_SUFFIX: &str = "trash";
+
+/// A free path to back up `target` to, named after the current UTC time
+pub fn next_backup_path(target: &str) -> String {
+    let stamp = now();
+    let mut backup = format!("{}{}{}{}", target, PREFIX, stamp, SUFFIX);
+    let mut count = 1;
+    while std::fs::symlink_metadata(&backup).is_ok() {
+        backup = format!("{}{}{}-{}{}", target, PREFIX, stamp, count, SUFFIX);
+        count += 1;
+    }
+    backup
+}
+
+/// Whether the path is named like a backup made by pono
+pub fn is_backup(path: &str) -> bool {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .and_then(|name| {
+            let (_, rest) = name.rsplit_once(PREFIX)?;
+            parse_stamp(rest.strip_suffix(SUFFIX)?)
+        })
+        .is_some()
+}
+
+/// A free path to move `target` to until it's removed with `purge`
+pub fn next_trash_path(target: &str) -> String {
+    let mut trash = format!("{}{}{}", target, PREFIX, TRASH_SUFFIX);
+    let mut count = 1;
+    while std::fs::symlink_metadata(&trash).is_ok() {
+        trash = format!("{}{}{}-{}", target, PREFIX, TRASH_SUFFIX, count);
+        count += 1;
+    }
+    trash
+}
+
+/// Whether the path is a target moved aside by `next_trash_path`
+pub fn is_trash(path: &str) -> bool {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .and_then(|name| {
+            let (_, rest) = name.rsplit_once(PREFIX)?;
+            let count = rest.strip_prefix(TRASH_SUFFIX)?;
+            Some(count.is_empty() || count.strip_prefix('-')?.parse::<u64>().is_ok())
+        })
+        .unwrap_or(false)
+}
+
+/// Removes a trashed file, symlink or directory with all its content
+pub fn purge(trash: &str) -> std::io::Result<()> {
+    let metadata = std::fs::symlink_metadata(trash)?;
+    if metadata.is_dir() {
+        std::fs::remove_dir_all(trash)
+    } else {
+        std::fs::remove_file(trash)
+    }
+}
+
+/// The backups pono made of `target`, oldest first
+pub fn list_backups(target: &str) -> Vec<String> {
+    let target = Path::new(target);
+    let (Some(dir), Some(name)) = (target.parent(), target.file_name()) else {
+        return vec![];
+    };
+    let prefix = format!("{}{}", name.to_string_lossy(), PREFIX);
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut backups: Vec<((u64, u64), String)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let order = file_name
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(SUFFIX))
+                .and_then(parse_stamp)?;
+            Some((order, entry.path().to_string_lossy().to_string()))
+        })
+        .collect();
+    backups.sort();
+    backups.into_iter().map(|(_, backup)| backup).collect()
+}
+
+/// The current UTC time as `YYYYMMDDhhmmss`, as used in backup names
+pub fn now() -> String {
+    timestamp(SystemTime::now())
+}
+
+/// Parses `{timestamp}` or `{timestamp}-{count}` into a sortable pair
+fn parse_stamp(stamp: &str) -> Option<(u64, u64)> {
+    match stamp.split_once('-') {
+        Some((time, count)) => Some((time.parse().ok()?, count.parse().ok()?)),
+        None => Some((stamp.parse().ok()?, 0)),
+    }
+}
+
+/// Formats the time as `YYYYMMDDhhmmss` in UTC
+fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
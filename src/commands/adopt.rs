@@ -0,0 +1,171 @@
+use std::io::Write;
+use std::path::Path;
+
+use crate::backup::next_backup_path;
+use crate::commands::find_pono;
+use crate::transaction::{Operation, Transaction};
+use crate::{
+    check_package, commit_transaction, link_source, path, print_plan, Configuration, GREEN, RED,
+    RESET,
+};
+
+/// Moves the current target into the pono source and links it back in its
+/// place. An existing source is shown against the target and, once
+/// confirmed, kept as a backup.
+pub fn run(config: &Configuration, pono: &str, yes: bool, dry_run: bool) {
+    let pono_info = find_pono(config, pono);
+
+    if pono_info.is_tree() {
+        println_color!(
+            RED,
+            "Pono {} can't be adopted: tree ponos aren't supported",
+            pono
+        );
+        std::process::exit(1);
+    }
+
+    let src_path = path(&pono_info.source);
+    let target_path = path(&pono_info.target);
+
+    match std::fs::symlink_metadata(&target_path) {
+        Ok(metadata) if metadata.is_symlink() => {
+            if check_package(pono_info).is_ok() {
+                println!("Pono {} is already linked", pono);
+                return;
+            }
+            println_color!(
+                RED,
+                "Refusing to adopt pono {}: target '{}' is a symlink",
+                pono,
+                pono_info.target
+            );
+            std::process::exit(1);
+        }
+        Ok(_) => (),
+        Err(_) => {
+            println_color!(
+                RED,
+                "Nothing to adopt for pono {}: target '{}' does not exist",
+                pono,
+                pono_info.target
+            );
+            println!("Debugging:");
+            println!(" - Run `pono enable {}` to link it", pono);
+            std::process::exit(1);
+        }
+    }
+
+    let mut transaction = Transaction::default();
+    if Path::new(&src_path).exists() && !confirm_replace(&src_path, &target_path, yes || dry_run) {
+        println_color!(RED, "Pono {} was not adopted", pono);
+        std::process::exit(1);
+    }
+    plan(
+        &mut transaction,
+        pono,
+        &src_path,
+        &target_path,
+        pono_info.relative(config),
+    );
+
+    if dry_run {
+        print_plan(&transaction, false);
+        return;
+    }
+
+    commit_transaction(config, transaction, |step| match &step.operation {
+        Operation::Rename { from, to } if *from == target_path => {
+            println_color!(GREEN, "  {}: {} -> {} (adopted)", step.pono, from, to);
+        }
+        Operation::Rename { to, .. } => {
+            println_color!(GREEN, "  {}: {} (backup)", step.pono, to);
+        }
+        Operation::Link { target, .. } => {
+            println_color!(GREEN, "  {}: {} (new link)", step.pono, target);
+        }
+        _ => (),
+    });
+}
+
+/// Adds the steps moving the target into the source, keeping a backup of an
+/// existing source, and linking the source back in place of the target
+pub fn plan(
+    transaction: &mut Transaction,
+    pono: &str,
+    src_path: &str,
+    target_path: &str,
+    relative: bool,
+) {
+    if Path::new(src_path).exists() {
+        transaction.add(
+            pono,
+            Operation::Rename {
+                from: src_path.to_string(),
+                to: next_backup_path(src_path),
+            },
+        );
+    } else {
+        // create the missing directories of the source, parents first
+        let missing: Vec<&Path> = Path::new(src_path)
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .collect();
+        for dir in missing.into_iter().rev() {
+            transaction.add(
+                pono,
+                Operation::CreateDir {
+                    path: dir.to_string_lossy().to_string(),
+                },
+            );
+        }
+    }
+    transaction.add(
+        pono,
+        Operation::Rename {
+            from: target_path.to_string(),
+            to: src_path.to_string(),
+        },
+    );
+    transaction.add(
+        pono,
+        Operation::Link {
+            source: link_source(src_path, target_path, relative),
+            target: target_path.to_string(),
+        },
+    );
+}
+
+/// Shows how an existing source differs from the target and asks whether to
+/// replace it, unless `yes`
+pub fn confirm_replace(src_path: &str, target_path: &str, yes: bool) -> bool {
+    println!(
+        "Source '{}' already exists, differences with the target '{}':",
+        src_path, target_path
+    );
+    print_diff(src_path, target_path);
+    yes || confirm(&format!("Replace '{}' with the target?", src_path))
+}
+
+/// Prints the differences between the source and the target with `diff`
+fn print_diff(src_path: &str, target_path: &str) {
+    let status = std::process::Command::new("diff")
+        .arg("-ru")
+        .arg(src_path)
+        .arg(target_path)
+        .status();
+    if status.is_err() {
+        println!("  (install diff to see the differences)");
+    }
+}
+
+/// Asks a yes or no question, anything but yes is a no
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush().ok();
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
@@ -0,0 +1,46 @@
+use crate::history::{display_time, load_for, Entry};
+use crate::{Configuration, RED, RESET};
+
+/// Prints the changes made with the config file, oldest first
+pub fn run(config: &Configuration) {
+    let entries = match load_for(&config.path) {
+        Ok(entries) => entries,
+        Err(err) => {
+            println_color!(RED, "Failed to read the history: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if entries.is_empty() {
+        println!("No changes recorded for {}", config.path);
+        return;
+    }
+
+    println!("History:");
+    for entry in &entries {
+        println!(
+            "  {} {} pono {}{}",
+            entry.id,
+            display_time(&entry.time),
+            entry.command,
+            label(entry, &entries)
+        );
+        for change in &entry.changes {
+            println!("      {}: {}", change.pono, change.operation);
+        }
+    }
+}
+
+fn label(entry: &Entry, entries: &[Entry]) -> String {
+    if entry.failed {
+        return " (failed, not rolled back)".to_string();
+    }
+    if !entry.undoes.is_empty() {
+        let ids: Vec<String> = entry.undoes.iter().map(|id| id.to_string()).collect();
+        return format!(" (undoes {})", ids.join(", "));
+    }
+    if entries.iter().any(|other| other.undoes.contains(&entry.id)) {
+        return " (undone)".to_string();
+    }
+    String::new()
+}
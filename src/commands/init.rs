@@ -0,0 +1,145 @@
+use std::path::Path;
+
+use crate::{path, GREEN, RED, RESET};
+
+/// Starter configurations written by `pono init`
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum Template {
+    /// Shell, git and editor dotfiles
+    #[default]
+    Dotfiles,
+    /// Git hooks shared in a repository
+    GitHooks,
+    /// Environment files switched with variants
+    EnvToggle,
+}
+
+impl Template {
+    fn content(&self) -> &'static str {
+        match self {
+            Template::Dotfiles => include_str!("../templates/dotfiles.toml"),
+            Template::GitHooks => include_str!("../templates/git-hooks.toml"),
+            Template::EnvToggle => include_str!("../templates/env-toggle.toml"),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Template::Dotfiles => "dotfiles",
+            Template::GitHooks => "git-hooks",
+            Template::EnvToggle => "env-toggle",
+        }
+    }
+}
+
+/// Common dotfiles offered as ponos when found in $HOME:
+/// (pono name, path relative to $HOME, suggested source)
+const DOTFILES: &[(&str, &str, &str)] = &[
+    ("zsh", ".zshrc", "./zshrc"),
+    ("bash", ".bashrc", "./bashrc"),
+    ("profile", ".profile", "./profile"),
+    ("git", ".gitconfig", "./gitconfig"),
+    ("vim", ".vimrc", "./vimrc"),
+    ("tmux", ".tmux.conf", "./tmux.conf"),
+    ("inputrc", ".inputrc", "./inputrc"),
+    ("nvim", ".config/nvim", "./nvim"),
+    ("fish", ".config/fish", "./fish"),
+    ("alacritty", ".config/alacritty", "./alacritty"),
+    ("kitty", ".config/kitty", "./kitty"),
+    ("starship", ".config/starship.toml", "./starship.toml"),
+];
+
+/// Writes a commented configuration from the template, refusing to overwrite
+/// an existing one unless forced
+pub fn run(config_path: &str, template: Template, force: bool, dry_run: bool) {
+    let expanded = path(config_path);
+    if Path::new(&expanded).exists() && !force {
+        println_color!(RED, "Config file {} already exists", config_path);
+        println!("Debugging:");
+        println!(" - Run `pono init --force` to overwrite it");
+        std::process::exit(1);
+    }
+
+    let candidates = find_dotfiles();
+    // ponos already in the template would be duplicated once uncommented
+    let new: Vec<_> = candidates
+        .iter()
+        .filter(|(name, ..)| !in_template(template.content(), name))
+        .collect();
+    let content = template.content().replacen(
+        "[ponos]\n",
+        &format!("[ponos]\n{}", candidates_section(&new)),
+        1,
+    );
+
+    if dry_run {
+        println!("Dry run (nothing was changed):");
+        println!(
+            "  write {} from the {} template",
+            config_path,
+            template.name()
+        );
+        print!("{}", content);
+        return;
+    }
+
+    if let Err(err) = std::fs::write(&expanded, content) {
+        println_color!(RED, "Failed to write {}", config_path);
+        println_color!(RED, "Reason: {}", err);
+        std::process::exit(1);
+    }
+
+    println_color!(
+        GREEN,
+        "Created {} from the {} template",
+        config_path,
+        template.name()
+    );
+    if !candidates.is_empty() {
+        let names: Vec<&str> = candidates.iter().map(|(name, ..)| *name).collect();
+        println!(
+            "Found dotfiles in $HOME: {}. Uncomment them in {} to manage them with pono",
+            names.join(", "),
+            config_path
+        );
+    }
+}
+
+/// Common dotfiles in $HOME that aren't symlinks yet
+fn find_dotfiles() -> Vec<(&'static str, &'static str, &'static str)> {
+    let Ok(home) = std::env::var("HOME") else {
+        return vec![];
+    };
+    DOTFILES
+        .iter()
+        .filter(|(_, target, _)| {
+            std::fs::symlink_metadata(Path::new(&home).join(target))
+                .is_ok_and(|metadata| !metadata.is_symlink())
+        })
+        .copied()
+        .collect()
+}
+
+/// Whether the template already has a commented pono with the name, either
+/// inline (`# zsh = {...}`) or as a table (`# [ponos.nvim]`)
+fn in_template(content: &str, name: &str) -> bool {
+    content.contains(&format!("\n# {} = ", name))
+        || content.contains(&format!("\n# [ponos.{}]", name))
+        || content.contains(&format!("\n# [ponos.\"{}\"]", name))
+}
+
+fn candidates_section(candidates: &[&(&str, &str, &str)]) -> String {
+    if candidates.is_empty() {
+        return String::new();
+    }
+    let mut section =
+        String::from("# Found in $HOME, move them next to this file and uncomment to link them\n");
+    for (name, target, source) in candidates {
+        section.push_str(&format!(
+            "# {} = {{ source = \"{}\", target = \"~/{}\" }}\n",
+            name, source, target
+        ));
+    }
+    section.push_str("#\n");
+    section
+}
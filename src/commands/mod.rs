@@ -0,0 +1,23 @@
+use crate::{Configuration, PonoDefinition, RED};
+
+pub mod adopt;
+pub mod history;
+pub mod init;
+pub mod restore;
+pub mod scan;
+pub mod sync;
+pub mod toggle;
+pub mod undo;
+
+/// Looks up a pono by its exact name, exiting when it doesn't exist
+fn find_pono<'a>(config: &'a Configuration, pono: &str) -> &'a PonoDefinition {
+    match config.ponos.get(pono) {
+        Some(pono) => pono,
+        _ => {
+            println!("{}Pono not found {} in ponos list", RED, pono);
+            println!("Debugging:");
+            println!(" - Run `pono list` to see the available ponos");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use crate::backup::list_backups;
+use crate::commands::find_pono;
+use crate::transaction::{Operation, Transaction};
+use crate::{commit_transaction, path, print_plan, Configuration, GREEN, RED, RESET};
+
+/// Puts the latest backup pono made of the target back in place. Only
+/// symlinks are removed from the target, anything else is left untouched.
+pub fn run(config: &Configuration, pono: &str, dry_run: bool) {
+    let pono_info = find_pono(config, pono);
+    let target_path = path(&pono_info.target);
+
+    let Some(backup) = list_backups(&target_path).pop() else {
+        println_color!(RED, "No backups found for pono {}", pono);
+        println!("Debugging:");
+        println!(" - Backups are created by `pono toggle {}`", pono);
+        std::process::exit(1);
+    };
+
+    let mut transaction = Transaction::default();
+    match std::fs::symlink_metadata(&target_path) {
+        Ok(metadata) if metadata.is_symlink() => {
+            transaction.add(
+                pono,
+                Operation::Unlink {
+                    target: target_path.clone(),
+                },
+            );
+        }
+        Ok(_) => {
+            println_color!(
+                RED,
+                "Refusing to restore pono {}: target '{}' isn't a symlink",
+                pono,
+                pono_info.target
+            );
+            std::process::exit(1);
+        }
+        Err(_) => (),
+    }
+    transaction.add(
+        pono,
+        Operation::Rename {
+            from: backup,
+            to: target_path,
+        },
+    );
+
+    if dry_run {
+        print_plan(&transaction, false);
+        return;
+    }
+
+    commit_transaction(config, transaction, |step| match &step.operation {
+        Operation::Unlink { target } => {
+            println_color!(GREEN, "  {}: {} (unlinked)", step.pono, target);
+        }
+        Operation::Rename { from, to } => {
+            println_color!(GREEN, "  {}: {} (restored from {})", step.pono, to, from);
+        }
+        _ => (),
+    });
+}
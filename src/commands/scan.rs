@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::{handle_config_error, load_config, path, GREEN, RED, RESET};
+
+/// Entries never turned into ponos
+const IGNORED: &[&str] = &[".git", ".DS_Store"];
+
+#[derive(Serialize)]
+struct ScannedPono {
+    source: String,
+    target: String,
+}
+
+#[derive(Serialize)]
+struct ScannedConfig {
+    ponos: BTreeMap<String, ScannedPono>,
+}
+
+/// Creates one pono per file or directory in `dir`, targeting the entry with
+/// the same name under `target_root`. Prints the ponos as TOML or, with
+/// `write`, appends the ones not configured yet to the config file.
+pub fn run(dir: &str, target_root: &str, config_path: &str, write: bool, dry_run: bool) {
+    let scanned = match scan(dir, target_root) {
+        Ok(scanned) => scanned,
+        Err(err) => {
+            println_color!(RED, "Failed to read directory {}", dir);
+            println_color!(RED, "Reason: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if !write {
+        print!("{}", to_toml(scanned));
+        return;
+    }
+
+    let existing = if Path::new(&path(config_path)).exists() {
+        handle_config_error(load_config(Some(config_path.to_string()))).ponos
+    } else {
+        Default::default()
+    };
+
+    let mut added = BTreeMap::new();
+    for (name, pono) in scanned {
+        if existing.contains_key(&name) {
+            println!("  {}: {} (skipped, already in config)", name, pono.source);
+            continue;
+        }
+        let same_source = existing
+            .iter()
+            .find(|(_, existing)| path(&existing.source) == path(&pono.source));
+        match same_source {
+            Some((other, _)) => {
+                println!(
+                    "  {}: {} (skipped, already used by {})",
+                    name, pono.source, other
+                );
+            }
+            None => {
+                added.insert(name, pono);
+            }
+        }
+    }
+
+    if added.is_empty() {
+        println!("No new ponos found in {}", dir);
+        return;
+    }
+
+    let names: Vec<String> = added.keys().cloned().collect();
+    let content = to_toml(added);
+    if dry_run {
+        println!("Dry run (nothing was changed):");
+        println!("  append to {}:", config_path);
+        print!("{}", content);
+        return;
+    }
+
+    if let Err(err) = append(&path(config_path), &content) {
+        println_color!(RED, "Failed to write {}", config_path);
+        println_color!(RED, "Reason: {}", err);
+        std::process::exit(1);
+    }
+    for name in names {
+        println_color!(GREEN, "  {}: added to {}", name, config_path);
+    }
+}
+
+fn scan(dir: &str, target_root: &str) -> std::io::Result<BTreeMap<String, ScannedPono>> {
+    let mut children = std::fs::read_dir(path(dir))?.collect::<Result<Vec<_>, _>>()?;
+    children.sort_by_key(|child| child.file_name());
+
+    let file_names: Vec<String> = children
+        .iter()
+        .map(|child| child.file_name().to_string_lossy().to_string())
+        .filter(|file_name| !IGNORED.contains(&file_name.as_str()))
+        .collect();
+
+    let mut ponos = BTreeMap::new();
+    for file_name in &file_names {
+        // `.zshrc` becomes the pono `zshrc`, unless there is also a `zshrc`
+        let name = match file_name.strip_prefix('.') {
+            Some(name) if !name.is_empty() && !file_names.iter().any(|f| f == name) => name,
+            _ => file_name,
+        };
+        ponos.insert(
+            name.to_string(),
+            ScannedPono {
+                source: Path::new(dir).join(file_name).to_string_lossy().to_string(),
+                target: Path::new(target_root)
+                    .join(file_name)
+                    .to_string_lossy()
+                    .to_string(),
+            },
+        );
+    }
+    Ok(ponos)
+}
+
+fn to_toml(ponos: BTreeMap<String, ScannedPono>) -> String {
+    toml::to_string(&ScannedConfig { ponos }).expect("Failed to serialize ponos")
+}
+
+/// Appends the ponos as new tables, keeping the config file as it is
+fn append(config_path: &str, content: &str) -> std::io::Result<()> {
+    let current = std::fs::read_to_string(config_path).unwrap_or_default();
+    let separator = match current.as_str() {
+        "" => "",
+        current if current.ends_with('\n') => "\n",
+        _ => "\n\n",
+    };
+    std::fs::write(config_path, format!("{}{}{}", current, separator, content))
+}
@@ -0,0 +1,224 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::backup;
+use crate::state::{absolute, LinkRecord, State};
+use crate::transaction::{Operation, Transaction};
+use crate::{
+    add_batch_hook, add_hook, check_link, check_package, commit_transaction, link_source,
+    linked_source, owned_link, path, plan_link, pono_dirs, pono_links, print_plan,
+    resolves_conflict, validate_package, Configuration, OnConflict, PonoDefinition, PonoError,
+    PonoLink, GREEN, RED, RESET,
+};
+
+/// What sync does with a link of a pono
+enum Change {
+    /// Creates the link, resolving what's in the way with the conflict policy
+    Link(PonoLink),
+    /// Points a broken or mismatched link to the source
+    Repair(PonoLink),
+}
+
+/// Makes the links of every pono match the configuration in one transaction,
+/// running the enable hooks of the ponos it changes. Ponos that can't be
+/// linked are reported and left alone. With `prune`, the links pono made for
+/// ponos removed from the config are removed. Adopted targets replace existing
+/// sources only once confirmed, or with `yes`.
+pub fn run(config: &Configuration, prune: bool, yes: bool, dry_run: bool) {
+    let mut names: Vec<&String> = config.ponos.keys().collect();
+    names.sort();
+
+    println!("Syncing ponos");
+    let mut in_sync = 0;
+    let mut invalid = 0;
+    let mut planned = vec![];
+    for name in names {
+        let package = &config.ponos[name];
+        if check_package(package).is_ok() {
+            in_sync += 1;
+            continue;
+        }
+        match diagnose(package) {
+            Ok((dirs, changes)) if dirs.is_empty() && changes.is_empty() => in_sync += 1,
+            Ok((dirs, changes)) => planned.push((name.clone(), dirs, changes)),
+            Err(err) => {
+                let reason = match err {
+                    PonoError::TargetAlreadyExists(err) => err,
+                    err => err.to_string(),
+                };
+                println_color!(RED, "  {}: {} (invalid)", name, package.target);
+                println_color!(RED, "  Reason: {}", reason);
+                invalid += 1;
+            }
+        }
+    }
+    let stale = match State::load() {
+        Ok(state) if prune => stale_links(config, &state),
+        Ok(_) => vec![],
+        Err(err) => {
+            println_color!(RED, "Failed to read the state: {}", err);
+            vec![]
+        }
+    };
+
+    let mut transaction = Transaction::default();
+    let mut trash = vec![];
+    let changed: Vec<String> = planned.iter().map(|(name, ..)| name.clone()).collect();
+    let has_changes = !planned.is_empty() || !stale.is_empty();
+    if has_changes {
+        add_batch_hook(&mut transaction, config, &changed, "enable", "before-all");
+    }
+    for (name, dirs, changes) in planned {
+        let on_conflict = config.ponos[&name].on_conflict(None);
+        let relative = config.ponos[&name].relative(config);
+        let source = path(&linked_source(&config.ponos[&name]));
+        add_hook(
+            &mut transaction,
+            config,
+            &name,
+            &source,
+            "enable",
+            "on-error",
+        );
+        add_hook(&mut transaction, config, &name, &source, "enable", "pre");
+        for dir in dirs {
+            transaction.add(&name, Operation::CreateDir { path: dir });
+        }
+        for change in changes {
+            match change {
+                Change::Link(link) => {
+                    plan_link(
+                        &mut transaction,
+                        &name,
+                        link,
+                        on_conflict,
+                        relative,
+                        yes || dry_run,
+                        &mut trash,
+                    );
+                }
+                Change::Repair(link) => transaction.add(
+                    &name,
+                    Operation::Relink {
+                        source: link_source(&link.source, &link.target, relative),
+                        target: link.target,
+                    },
+                ),
+            }
+        }
+        add_hook(&mut transaction, config, &name, &source, "enable", "post");
+    }
+    for link in stale {
+        transaction.add(
+            &link.pono,
+            Operation::Unlink {
+                target: link.target,
+            },
+        );
+    }
+    if has_changes {
+        add_batch_hook(&mut transaction, config, &changed, "enable", "after-all");
+    }
+
+    if dry_run {
+        print_plan(&transaction, invalid > 0);
+        return;
+    }
+
+    let (mut linked, mut repaired, mut pruned) = (0, 0, 0);
+    commit_transaction(config, transaction, |step| match &step.operation {
+        Operation::Unlink { target } => {
+            println_color!(GREEN, "  {}: {} (pruned)", step.pono, target);
+            pruned += 1;
+        }
+        Operation::Link { target, .. } | Operation::Relink { target, .. } => {
+            let pono_definition = &config.ponos[&step.pono];
+            let target = if pono_definition.is_tree() {
+                target
+            } else {
+                &pono_definition.target
+            };
+            if let Operation::Relink { .. } = step.operation {
+                println_color!(GREEN, "  {}: {} (fixed link)", step.pono, target);
+                repaired += 1;
+            } else {
+                println_color!(GREEN, "  {}: {} (new link)", step.pono, target);
+                linked += 1;
+            }
+        }
+        Operation::Rename { from, to } if trash.contains(to) => {
+            println_color!(GREEN, "  {}: {} (overwritten)", step.pono, from);
+        }
+        Operation::Rename { to, .. } if backup::is_backup(to) => {
+            println_color!(GREEN, "  {}: {} (backup)", step.pono, to);
+        }
+        Operation::Rename { from, to } => {
+            println_color!(GREEN, "  {}: {} -> {} (adopted)", step.pono, from, to);
+        }
+        _ => (),
+    });
+
+    for path in trash {
+        if let Err(err) = backup::purge(&path) {
+            println_color!(RED, "Failed to remove {}: {}", path, err);
+        }
+    }
+
+    println!(
+        "Synced: {} new links, {} fixed, {} pruned, {} ponos already in sync",
+        linked, repaired, pruned, in_sync
+    );
+    if invalid > 0 {
+        println_color!(RED, "{} invalid ponos were left untouched", invalid);
+        std::process::exit(1);
+    }
+}
+
+/// Lists the directories to create and the links to change for the pono to
+/// match its configuration
+fn diagnose(package: &PonoDefinition) -> Result<(Vec<String>, Vec<Change>), PonoError> {
+    let on_conflict = package.on_conflict(None);
+    // links in the way are repaired, only files and directories can conflict
+    let policy = match on_conflict {
+        OnConflict::Adopt if resolves_conflict(on_conflict, &path(&package.target)) => on_conflict,
+        _ => OnConflict::Skip,
+    };
+    validate_package(package, policy)?;
+
+    let dirs = pono_dirs(package)?
+        .into_iter()
+        .filter(|dir| !Path::new(dir).exists())
+        .collect();
+    let mut changes = vec![];
+    for link in pono_links(package)? {
+        match check_link(&link.source, &link.target, &link.source, &link.target) {
+            Ok(_) => (),
+            Err(PonoError::NotFound(_)) => changes.push(Change::Link(link)),
+            Err(PonoError::Dangling(_)) | Err(PonoError::LinkMismatch(_)) => {
+                changes.push(Change::Repair(link))
+            }
+            Err(PonoError::NotSymlink(_)) if resolves_conflict(on_conflict, &link.target) => {
+                changes.push(Change::Link(link))
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok((dirs, changes))
+}
+
+/// Links recorded in the state for ponos of the config that no pono links
+/// anymore, and that still point to the source they were made for
+pub fn stale_links(config: &Configuration, state: &State) -> Vec<LinkRecord> {
+    let linked: BTreeSet<String> = config
+        .ponos
+        .values()
+        .flat_map(|package| pono_links(package).unwrap_or_default())
+        .map(|link| absolute(&link.target))
+        .collect();
+    state
+        .links_of(&config.path)
+        .filter(|link| !linked.contains(&link.target))
+        .filter(|link| owned_link(&link.source, &link.target, &link.source, &link.target).is_ok())
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,144 @@
+use crate::backup::{list_backups, next_backup_path};
+use crate::commands::find_pono;
+use crate::transaction::{Operation, Transaction};
+use crate::{
+    active_variant, add_batch_hook, add_hook, check_link, check_package, commit_transaction,
+    link_source, next_variant, path, print_plan, Configuration, GREEN, RED, RESET,
+};
+
+/// Links the pono in place of its target, pushing what was there onto the
+/// backup stack. Toggling a pono that is already linked switches back to the
+/// latest backup. Ponos with variants switch to the given or next variant.
+pub fn run(config: &Configuration, pono: &str, variant: Option<String>, dry_run: bool) {
+    let pono_info = find_pono(config, pono);
+
+    if pono_info.is_tree() {
+        println_color!(
+            RED,
+            "Pono {} can't be toggled: tree ponos aren't supported",
+            pono
+        );
+        std::process::exit(1);
+    }
+
+    let variant = match (&pono_info.variants, variant) {
+        (Some(variants), Some(name)) if !variants.contains_key(&name) => {
+            println_color!(RED, "Variant {} not found for pono {}", name, pono);
+            println!("Debugging:");
+            println!(" - Run `pono list` to see the available variants");
+            std::process::exit(1);
+        }
+        (None, Some(_)) => {
+            println_color!(RED, "Pono {} has no variants", pono);
+            std::process::exit(1);
+        }
+        (Some(_), Some(name)) => Some(name),
+        (Some(_), None) => next_variant(pono_info),
+        (None, None) => None,
+    };
+
+    let src_path = match (&pono_info.variants, &variant) {
+        (Some(variants), Some(name)) => path(&variants[name].source),
+        _ => path(&pono_info.source),
+    };
+    let target_path = path(&pono_info.target);
+    let relative = pono_info.relative(config);
+    let target_metadata = std::fs::symlink_metadata(&target_path);
+
+    let mut transaction = Transaction::default();
+    let ponos = [pono.to_string()];
+    add_batch_hook(&mut transaction, config, &ponos, "toggle", "before-all");
+    add_hook(
+        &mut transaction,
+        config,
+        pono,
+        &src_path,
+        "toggle",
+        "on-error",
+    );
+    add_hook(&mut transaction, config, pono, &src_path, "toggle", "pre");
+    if variant.is_some() && active_variant(pono_info).is_some() {
+        // switch between variants in a single step
+        transaction.add(
+            pono,
+            Operation::Relink {
+                source: link_source(&src_path, &target_path, relative),
+                target: target_path.clone(),
+            },
+        );
+    } else if variant.is_none()
+        && check_link(
+            &src_path,
+            &target_path,
+            &pono_info.source,
+            &pono_info.target,
+        )
+        .is_ok()
+    {
+        // already toggled, switch back to what was there before
+        transaction.add(
+            pono,
+            Operation::Unlink {
+                target: target_path.clone(),
+            },
+        );
+        if let Some(backup) = list_backups(&target_path).pop() {
+            transaction.add(
+                pono,
+                Operation::Rename {
+                    from: backup,
+                    to: target_path.clone(),
+                },
+            );
+        }
+    } else {
+        if target_metadata.is_ok() {
+            transaction.add(
+                pono,
+                Operation::Rename {
+                    from: target_path.clone(),
+                    to: next_backup_path(&target_path),
+                },
+            );
+        }
+        transaction.add(
+            pono,
+            Operation::Link {
+                source: link_source(&src_path, &target_path, relative),
+                target: target_path.clone(),
+            },
+        );
+    }
+    add_hook(&mut transaction, config, pono, &src_path, "toggle", "post");
+    add_batch_hook(&mut transaction, config, &ponos, "toggle", "after-all");
+
+    if dry_run {
+        if let Err(err) = check_package(pono_info) {
+            println!("  {} {} (current state)", pono, pono_info.target);
+            println!("  Reason: {}", err);
+        }
+        print_plan(&transaction, false);
+        return;
+    }
+
+    commit_transaction(config, transaction, |step| match &step.operation {
+        Operation::Rename { to, .. } if *to == target_path => {
+            println_color!(GREEN, "  {}: {} (restored)", step.pono, to);
+        }
+        Operation::Rename { to, .. } => {
+            println_color!(GREEN, "  {}: {} (backup)", step.pono, to);
+        }
+        Operation::Unlink { target } => {
+            println_color!(GREEN, "  {}: {} (unlinked)", step.pono, target);
+        }
+        Operation::Link { target, .. } | Operation::Relink { target, .. } => match &variant {
+            Some(name) => {
+                println_color!(GREEN, "  {}: {} (variant {})", step.pono, target, name);
+            }
+            None => {
+                println_color!(GREEN, "  {}: {} (new link)", step.pono, target);
+            }
+        },
+        _ => (),
+    });
+}
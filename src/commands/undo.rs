@@ -0,0 +1,64 @@
+use crate::history::{display_time, load_for};
+use crate::transaction::Transaction;
+use crate::{commit_transaction, print_plan, Configuration, GREEN, RED, RESET};
+
+/// Reverts the changes of the last `n` invocations made with the config file,
+/// newest first, in a single transaction. Undoing is recorded like any other
+/// change and undone invocations are skipped next time.
+pub fn run(config: &Configuration, n: usize, dry_run: bool) {
+    let entries = match load_for(&config.path) {
+        Ok(entries) => entries,
+        Err(err) => {
+            println_color!(RED, "Failed to read the history: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let undone: Vec<u64> = entries
+        .iter()
+        .flat_map(|entry| entry.undoes.iter().copied())
+        .collect();
+    let to_undo: Vec<_> = entries
+        .iter()
+        .rev()
+        .filter(|entry| entry.undoes.is_empty() && !undone.contains(&entry.id))
+        .take(n)
+        .collect();
+    if to_undo.is_empty() {
+        println!("Nothing to undo for {}", config.path);
+        return;
+    }
+
+    let mut transaction = Transaction::default();
+    for entry in &to_undo {
+        println!(
+            "Undoing {} {} pono {}",
+            entry.id,
+            display_time(&entry.time),
+            entry.command
+        );
+        for change in entry.changes.iter().rev() {
+            match &change.revert {
+                Some(revert) => transaction.add(&change.pono, revert.clone()),
+                None => {
+                    println_color!(
+                        RED,
+                        "  {}: {} (can't be undone)",
+                        change.pono,
+                        change.operation
+                    );
+                }
+            }
+        }
+    }
+    transaction.set_undoes(to_undo.iter().map(|entry| entry.id).collect());
+
+    if dry_run {
+        print_plan(&transaction, false);
+        return;
+    }
+
+    commit_transaction(config, transaction, |step| {
+        println_color!(GREEN, "  {}: {} (undone)", step.pono, step.operation);
+    });
+}
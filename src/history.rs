@@ -0,0 +1,161 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::backup;
+use crate::state::{absolute, state_path};
+use crate::transaction::{Operation, Step};
+
+/// A filesystem change kept in the history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub pono: String,
+    pub operation: Operation,
+    /// The operation reverting the change, none when it can't be undone
+    pub revert: Option<Operation>,
+}
+
+/// A pono invocation that changed the filesystem
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    /// When the changes were made, as `YYYYMMDDhhmmss` in UTC
+    pub time: String,
+    /// The arguments pono was run with, e.g. `enable zsh`
+    pub command: String,
+    /// Path of the config file the ponos were defined in
+    pub config: String,
+    /// The entries this one reverted, for `pono undo`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub undoes: Vec<u64>,
+    /// The invocation failed and these changes couldn't be rolled back
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub failed: bool,
+    pub changes: Vec<Change>,
+}
+
+/// Where the history is kept, an append-only file with one JSON entry per
+/// line next to the state
+pub fn history_path() -> PathBuf {
+    state_path().with_file_name("history.jsonl")
+}
+
+/// Reads every entry of the history, oldest first
+pub fn load() -> Result<Vec<Entry>, String> {
+    let history_path = history_path();
+    let content = match std::fs::read_to_string(&history_path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(format!("{}: {}", history_path.display(), err)),
+    };
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .map_err(|err| format!("{}:{}: {}", history_path.display(), index + 1, err))
+        })
+        .collect()
+}
+
+/// The entries made with the config file, oldest first
+pub fn load_for(config: &str) -> Result<Vec<Entry>, String> {
+    let config = absolute(config);
+    Ok(load()?
+        .into_iter()
+        .filter(|entry| entry.config == config)
+        .collect())
+}
+
+/// Appends the applied steps as a new entry, `failed` when they are what a
+/// failed invocation couldn't roll back. Hooks aren't kept, they change
+/// nothing pono can revert.
+pub fn append(config: &str, steps: &[Step], undoes: &[u64], failed: bool) -> Result<(), String> {
+    let changes: Vec<Change> = steps
+        .iter()
+        .filter(|step| !matches!(step.operation, Operation::Hook(_)))
+        .map(|step| Change {
+            pono: step.pono.clone(),
+            operation: absolute_paths(&step.operation),
+            revert: match &step.operation {
+                // overwritten targets are removed once the links are in place
+                Operation::Rename { to, .. } if backup::is_trash(to) => None,
+                _ => step.revert.as_ref().map(absolute_paths),
+            },
+        })
+        .collect();
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let entry = Entry {
+        id: load()?.last().map_or(1, |entry| entry.id + 1),
+        time: backup::now(),
+        command: std::env::args().skip(1).collect::<Vec<_>>().join(" "),
+        config: absolute(config),
+        undoes: undoes.to_vec(),
+        failed,
+        changes,
+    };
+    let history_path = history_path();
+    let line = serde_json::to_string(&entry).map_err(|err| err.to_string())?;
+    history_path
+        .parent()
+        .map(std::fs::create_dir_all)
+        .transpose()
+        .and_then(|_| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&history_path)
+        })
+        .and_then(|mut file| file.write_all(format!("{}\n", line).as_bytes()))
+        .map_err(|err| format!("{}: {}", history_path.display(), err))
+}
+
+/// Formats a `YYYYMMDDhhmmss` time as `YYYY-MM-DD hh:mm:ss`
+pub fn display_time(time: &str) -> String {
+    if time.len() != 14 || !time.is_ascii() {
+        return time.to_string();
+    }
+    format!(
+        "{}-{}-{} {}:{}:{}",
+        &time[0..4],
+        &time[4..6],
+        &time[6..8],
+        &time[8..10],
+        &time[10..12],
+        &time[12..14]
+    )
+}
+
+/// The operation with the paths it acts on made absolute, so it can be undone
+/// from any directory. Link sources are kept as written in the link.
+fn absolute_paths(operation: &Operation) -> Operation {
+    match operation {
+        Operation::Link { source, target } => Operation::Link {
+            source: source.clone(),
+            target: absolute(target),
+        },
+        Operation::Unlink { target } => Operation::Unlink {
+            target: absolute(target),
+        },
+        Operation::Relink { source, target } => Operation::Relink {
+            source: source.clone(),
+            target: absolute(target),
+        },
+        Operation::Rename { from, to } => Operation::Rename {
+            from: absolute(from),
+            to: absolute(to),
+        },
+        Operation::CreateDir { path } => Operation::CreateDir {
+            path: absolute(path),
+        },
+        Operation::RemoveDir { path } => Operation::RemoveDir {
+            path: absolute(path),
+        },
+        Operation::Hook(_) => operation.clone(),
+    }
+}
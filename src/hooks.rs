@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::PonoError;
+
+/// How long the error output of a finished hook is read for
+const STDERR_GRACE: Duration = Duration::from_millis(100);
+
+/// What a hook runs
+#[derive(Debug, Clone)]
+pub enum Program {
+    /// A script run with `{shell} -c`
+    Script { shell: String, script: String },
+    /// A program and its arguments, run without a shell
+    Args(Vec<String>),
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Program::Script { script, .. } => write!(f, "{}", script),
+            Program::Args(args) => write!(f, "{}", args.join(" ")),
+        }
+    }
+}
+
+/// A hook ready to run for a pono
+#[derive(Debug, Clone)]
+pub struct HookRun {
+    /// e.g. `pre-enable` or `on-error`
+    pub event: String,
+    pub program: Program,
+    /// Go on even if the command exits with a non-zero code
+    pub allow_failure: bool,
+    /// Directory to run the command in, the current one when none
+    pub cwd: Option<String>,
+    /// `PONO_*` variables describing the pono followed by the hook `env`
+    pub env: BTreeMap<String, String>,
+    /// Kills the command when it runs for longer
+    pub timeout: Option<Duration>,
+    /// Hides the output of the command, errors are still reported
+    pub quiet: bool,
+}
+
+impl HookRun {
+    /// Runs the command. The output is shown as it's printed, unless quiet,
+    /// and the error output is also kept to report failures. Pono doesn't
+    /// wait for what the command starts in the background.
+    pub fn run(&self) -> Result<(), PonoError> {
+        let mut command = match &self.program {
+            Program::Script { shell, script } => {
+                let mut command = std::process::Command::new(shell);
+                command.arg("-c").arg(script);
+                command
+            }
+            Program::Args(args) => {
+                let Some((program, args)) = args.split_first() else {
+                    return Err(PonoError::Unhandled(format!(
+                        "The {} hook has no command to run",
+                        self.event
+                    )));
+                };
+                let mut command = std::process::Command::new(program);
+                command.args(args);
+                command
+            }
+        };
+        command
+            .envs(&self.env)
+            .stdin(Stdio::null())
+            .stdout(if self.quiet {
+                Stdio::null()
+            } else {
+                Stdio::inherit()
+            })
+            .stderr(Stdio::piped());
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        if self.timeout.is_some() {
+            // its own process group, to kill what the command started on timeout
+            command.process_group(0);
+        }
+
+        let mut child = command.spawn().map_err(|err| {
+            PonoError::Unhandled(format!("Failed to execute {} hook: {}", self.event, err))
+        })?;
+        // read the error output while waiting, so a full pipe can't block the hook
+        let mut stderr_pipe = child.stderr.take().expect("stderr is piped");
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let quiet = self.quiet;
+        std::thread::spawn(move || {
+            let mut buffer = [0; 4096];
+            while let Ok(read @ 1..) = stderr_pipe.read(&mut buffer) {
+                if !quiet {
+                    std::io::stderr().write_all(&buffer[..read]).ok();
+                }
+                if sender.send(buffer[..read].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let started = Instant::now();
+        let status = loop {
+            let status = child.try_wait().map_err(|err| {
+                PonoError::Unhandled(format!("Failed to wait for {} hook: {}", self.event, err))
+            })?;
+            if let Some(status) = status {
+                break status;
+            }
+            if let Some(timeout) = self.timeout.filter(|timeout| started.elapsed() >= *timeout) {
+                std::process::Command::new("kill")
+                    .args(["-KILL", "--"])
+                    .arg(format!("-{}", child.id()))
+                    .status()
+                    .ok();
+                child.kill().ok();
+                child.wait().ok();
+                return Err(PonoError::HookTimeout {
+                    event: self.event.clone(),
+                    timeout,
+                });
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        // processes the command left in the background keep the pipe open,
+        // so the rest of the output is only waited for a moment
+        let deadline = Instant::now() + STDERR_GRACE;
+        let mut stderr = vec![];
+        while let Ok(chunk) =
+            receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            stderr.extend(chunk);
+        }
+        if !status.success() && !self.allow_failure {
+            return Err(PonoError::HookFailed {
+                event: self.event.clone(),
+                code: status.code(),
+                stderr: String::from_utf8_lossy(&stderr).to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Display for HookRun {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "run {} hook: {}", self.event, self.program)
+    }
+}
+
+/// A duration written as `500ms`, `30s`, `5m` or `1h`, seconds when no unit
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Timeout(pub Duration);
+
+impl TryFrom<String> for Timeout {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let split = value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len());
+        let (amount, unit) = value.split_at(split);
+        let amount: u64 = amount
+            .parse()
+            .map_err(|_| format!("Invalid timeout `{}`, use e.g. 30s or 5m", value))?;
+        let duration = match unit.trim() {
+            "ms" => Duration::from_millis(amount),
+            "" | "s" => Duration::from_secs(amount),
+            "m" => Duration::from_secs(amount * 60),
+            "h" => Duration::from_secs(amount * 3_600),
+            _ => return Err(format!("Invalid timeout `{}`, use e.g. 30s or 5m", value)),
+        };
+        Ok(Timeout(duration))
+    }
+}
@@ -1,15 +1,25 @@
-use clap::builder::PossibleValue;
+use clap::builder::{PossibleValue, StringValueParser, TypedValueParser};
 use clap::{CommandFactory, Parser, Subcommand, ValueHint};
 use clap_complete::{generate, Shell};
-use serde::Deserialize;
-use std::collections::HashMap;
+use commands::init::Template;
+use hooks::{HookRun, Program, Timeout};
+use report::{print_reports, Format, PonoReport};
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use state::State;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fmt::{Display, Formatter};
-use std::os::unix::fs::symlink;
 use std::path::PathBuf;
+use transaction::{Operation, Step, Transaction};
 
 pub const CLI_NAME: &str = "pono";
 
+/// Label of the steps run for all the ponos of a command, such as the
+/// top-level hooks. Patterns select ponos, so no pono is named like this.
+const ALL_PONOS: &str = "*";
+
 // ANSI color codes for terminal output
 const GREEN: &str = "\x1b[32m";
 const RED: &str = "\x1b[31m";
@@ -27,10 +37,185 @@ macro_rules! println_color {
     };
 }
 
+mod backup;
+mod commands;
+mod history;
+mod hooks;
+mod report;
+mod state;
+mod transaction;
+mod tree;
+
+/// A hook: a command, a table with options or a list of them run in order
+#[derive(Debug)]
+enum Hook {
+    One(HookCommand),
+    Many(Vec<HookCommand>),
+}
+
+/// A hook command, either as a string run with `sh -c` or as a table
+#[derive(Debug)]
+enum HookCommand {
+    Command(String),
+    Table(HookTable),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HookTable {
+    run: HookRunField,
+    /// Shell running a string command (default: sh)
+    shell: Option<String>,
+    /// Kills the command after the given time, e.g. `30s` or `5m`
+    timeout: Option<Timeout>,
+    /// Hide the output of the command
+    #[serde(default)]
+    quiet: bool,
+    /// Go on even if the command exits with a non-zero code
+    #[serde(default)]
+    allow_failure: bool,
+    /// Directory to run the command in (default: the current one)
+    cwd: Option<String>,
+    /// Extra environment variables for the command
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+/// A command run by a shell, or a program and its arguments run without one
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum HookRunField {
+    Command(String),
+    Args(Vec<String>),
+}
+
+impl Hook {
+    fn commands(&self) -> &[HookCommand] {
+        match self {
+            Hook::One(command) => std::slice::from_ref(command),
+            Hook::Many(commands) => commands,
+        }
+    }
+}
+
+// Hooks are picked by the type of the value rather than with
+// `#[serde(untagged)]`, which would hide the errors of the tables
+
+impl<'de> Deserialize<'de> for Hook {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct HookVisitor;
+
+        impl<'de> Visitor<'de> for HookVisitor {
+            type Value = Hook;
+
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("a command, a table or a list of them")
+            }
+
+            fn visit_str<E: de::Error>(self, command: &str) -> Result<Hook, E> {
+                HookCommandVisitor.visit_str(command).map(Hook::One)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, table: A) -> Result<Hook, A::Error> {
+                HookCommandVisitor.visit_map(table).map(Hook::One)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut list: A) -> Result<Hook, A::Error> {
+                let mut commands = Vec::new();
+                while let Some(command) = list.next_element()? {
+                    commands.push(command);
+                }
+                Ok(Hook::Many(commands))
+            }
+        }
+
+        deserializer.deserialize_any(HookVisitor)
+    }
+}
+
+struct HookCommandVisitor;
+
+impl<'de> Visitor<'de> for HookCommandVisitor {
+    type Value = HookCommand;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a command or a table")
+    }
+
+    fn visit_str<E: de::Error>(self, command: &str) -> Result<HookCommand, E> {
+        Ok(HookCommand::Command(command.to_string()))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, table: A) -> Result<HookCommand, A::Error> {
+        HookTable::deserialize(MapAccessDeserializer::new(table)).map(HookCommand::Table)
+    }
+}
+
+impl<'de> Deserialize<'de> for HookCommand {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(HookCommandVisitor)
+    }
+}
+
+/// Commands run around the changes made to a pono
 #[derive(Debug, Deserialize)]
 struct Hooks {
-    pre_enable: Option<String>,
-    pre_disable: Option<String>,
+    pre_enable: Option<Hook>,
+    post_enable: Option<Hook>,
+    pre_disable: Option<Hook>,
+    post_disable: Option<Hook>,
+    pre_toggle: Option<Hook>,
+    post_toggle: Option<Hook>,
+    /// Runs after the rollback when a change fails
+    on_error: Option<Hook>,
+}
+
+impl Hooks {
+    /// The hook to run on the event, e.g. `pre-enable`
+    fn hook(&self, event: &str) -> Option<&Hook> {
+        match event {
+            "pre-enable" => self.pre_enable.as_ref(),
+            "post-enable" => self.post_enable.as_ref(),
+            "pre-disable" => self.pre_disable.as_ref(),
+            "post-disable" => self.post_disable.as_ref(),
+            "pre-toggle" => self.pre_toggle.as_ref(),
+            "post-toggle" => self.post_toggle.as_ref(),
+            "on-error" => self.on_error.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Commands run once around all the ponos changed by a command
+#[derive(Debug, Deserialize)]
+struct BatchHooks {
+    before_all: Option<Hook>,
+    after_all: Option<Hook>,
+}
+
+/// The top-level `[hooks]` table
+#[derive(Debug, Deserialize)]
+struct GlobalHooks {
+    enable: Option<BatchHooks>,
+    disable: Option<BatchHooks>,
+    toggle: Option<BatchHooks>,
+}
+
+impl GlobalHooks {
+    /// The hook to run at `stage` (`before-all` or `after-all`) of the action
+    fn hook(&self, action: &str, stage: &str) -> Option<&Hook> {
+        let hooks = match action {
+            "enable" => self.enable.as_ref(),
+            "disable" => self.disable.as_ref(),
+            "toggle" => self.toggle.as_ref(),
+            _ => None,
+        }?;
+        match stage {
+            "before-all" => hooks.before_all.as_ref(),
+            "after-all" => hooks.after_all.as_ref(),
+            _ => None,
+        }
+    }
 }
 
 /// pono - pack and organize symlinks once
@@ -44,45 +229,206 @@ struct Args {
     /// Optional config file path (default: pono.toml)
     #[clap(short, long, value_hint = ValueHint::FilePath)]
     config: Option<String>,
+
+    /// Print the operations to perform without touching the filesystem or running hooks
+    #[clap(long, global = true)]
+    dry_run: bool,
+}
+
+/// How the source of a pono is linked to its target
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// Links the source as a whole
+    #[default]
+    Link,
+    /// Creates the source directories in the target and links each file on its own
+    Tree,
+}
+
+/// What `enable` does when something already exists at a target
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum OnConflict {
+    /// Stop without changing anything
+    #[default]
+    Fail,
+    /// Move the target aside to a timestamped backup
+    Backup,
+    /// Remove the target
+    Overwrite,
+    /// Leave the target as it is and link the other ponos
+    Skip,
+    /// Move the target into the pono source
+    Adopt,
+}
+
+/// One of the sources a pono with variants can link to its target
+#[derive(Debug, Deserialize)]
+struct Variant {
+    source: String,
+    /// The variant linked by `enable`, exactly one per pono
+    #[serde(default)]
+    default: bool,
 }
 
 #[derive(Debug, Deserialize)]
 struct PonoDefinition {
+    /// Filled with the default variant source for ponos with variants
+    #[serde(default)]
     source: String,
     target: String,
+    /// Named sources sharing the target, switched with `toggle`
+    variants: Option<BTreeMap<String, Variant>>,
+    /// Tags to select the pono with `@tag` or `--tag`
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    mode: Mode,
+    /// Glob patterns of the files to link when the source is a directory
+    include: Option<Vec<String>>,
+    /// Glob patterns of the files to skip when the source is a directory
+    exclude: Option<Vec<String>>,
+    /// What to do when the target already exists
+    on_conflict: Option<OnConflict>,
+    /// Link to the source relative to the target directory, overrides the
+    /// config `relative`
+    relative: Option<bool>,
     hooks: Option<Hooks>,
 }
 
+impl PonoDefinition {
+    /// Whether the pono links each file of its source on its own. Directory
+    /// sources with include or exclude patterns are always linked this way.
+    fn is_tree(&self) -> bool {
+        let has_patterns = self.include.is_some() || self.exclude.is_some();
+        self.mode == Mode::Tree
+            || (has_patterns && std::path::Path::new(&path(&self.source)).is_dir())
+    }
+
+    /// The conflict policy given in the command line, or the pono one
+    fn on_conflict(&self, cli: Option<OnConflict>) -> OnConflict {
+        cli.or(self.on_conflict).unwrap_or_default()
+    }
+
+    /// Whether the links point to the source with a relative path
+    fn relative(&self, config: &Configuration) -> bool {
+        self.relative.unwrap_or(config.relative)
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Enables all or a space-separated list of ponos
     Enable {
-        /// Optional list of ponos to enable (default: all)
-        #[clap(value_parser(suggest_ponos()))]
+        /// Optional list of ponos or @groups to enable (default: all)
+        #[clap(value_parser = Suggestions(suggest_ponos))]
         ponos: Option<Vec<String>>,
+        /// Enable the ponos with the given tag or group, can be repeated
+        #[clap(long = "tag", value_parser = Suggestions(suggest_groups))]
+        tags: Vec<String>,
+        /// What to do with existing targets, overrides the pono `on_conflict`
+        #[clap(long, value_enum)]
+        on_conflict: Option<OnConflict>,
+        /// Replace existing sources when adopting targets without asking
+        #[clap(short, long)]
+        yes: bool,
     },
     /// Disable all or a space-separated list of ponos
     Disable {
-        /// Optional list of ponos to disable (default: all)
-        #[clap(value_parser(suggest_ponos()))]
+        /// Optional list of ponos or @groups to disable (default: all)
+        #[clap(value_parser = Suggestions(suggest_ponos))]
         ponos: Option<Vec<String>>,
+        /// Disable the ponos with the given tag or group, can be repeated
+        #[clap(long = "tag", value_parser = Suggestions(suggest_groups))]
+        tags: Vec<String>,
+        /// Back up targets that weren't linked by pono instead of refusing
+        #[clap(long)]
+        force: bool,
     },
-    /// Toggle the given pono without validation (backup {target}.bak)
+    /// Toggle the given pono without validation, backing up the current target,
+    /// or switch the variant of a pono with variants
     Toggle {
         /// Required pono to toggle
-        #[clap(value_parser(suggest_ponos()))]
+        #[clap(value_parser = Suggestions(suggest_ponos))]
+        pono: String,
+        /// Optional variant to switch to (default: the next variant)
+        #[clap(value_parser = Suggestions(suggest_variants))]
+        variant: Option<String>,
+    },
+    /// Restore the latest target backup made by toggle
+    Restore {
+        /// Required pono to restore
+        #[clap(value_parser = Suggestions(suggest_ponos))]
         pono: String,
     },
+    /// Move the current target into the pono source and link it in its place
+    Adopt {
+        /// Required pono to adopt
+        #[clap(value_parser = Suggestions(suggest_ponos))]
+        pono: String,
+        /// Replace an existing source without asking
+        #[clap(short, long)]
+        yes: bool,
+    },
+    /// Make the links match the configuration, creating missing links and
+    /// repairing broken or mismatched ones
+    Sync {
+        /// Also remove links to the config directory no pono owns anymore
+        #[clap(long)]
+        prune: bool,
+        /// Replace existing sources when adopting targets without asking
+        #[clap(short, long)]
+        yes: bool,
+    },
+    /// Show the changes pono made with the config file
+    History,
+    /// Revert the changes of the last invocations made with the config file
+    Undo {
+        /// Number of invocations to revert
+        #[clap(default_value_t = 1)]
+        n: usize,
+    },
     /// Display the status of all ponos
     #[clap(visible_alias = "st")]
     Status {
-        /// Optional list of ponos to check (default: all)
-        #[clap(value_parser(suggest_ponos()))]
+        /// Optional list of ponos or @groups to check (default: all)
+        #[clap(value_parser = Suggestions(suggest_ponos))]
         ponos: Option<Vec<String>>,
+        /// Check the ponos with the given tag or group, can be repeated
+        #[clap(long = "tag", value_parser = Suggestions(suggest_groups))]
+        tags: Vec<String>,
+        /// Output format
+        #[clap(long, value_enum, default_value_t)]
+        format: Format,
     },
     /// List all ponos in the configuration
     #[clap(visible_alias = "ls")]
-    List,
+    List {
+        /// Output format
+        #[clap(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// Write a starter config file from a template
+    Init {
+        /// Template to start from
+        #[clap(long, value_enum, default_value_t)]
+        template: Template,
+        /// Overwrite the config file when it already exists
+        #[clap(long)]
+        force: bool,
+    },
+    /// Create a pono for each file or directory in the given directory
+    Scan {
+        /// Directory to scan, e.g. a stow package
+        #[clap(value_hint = ValueHint::DirPath)]
+        dir: String,
+        /// Directory where the ponos are linked
+        #[clap(long, default_value = "~", value_hint = ValueHint::DirPath)]
+        target_root: String,
+        /// Append the new ponos to the config file instead of printing them
+        #[clap(long)]
+        write: bool,
+    },
 
     /// Generate autocompletion based on $SHELL or the specified shell
     Completions { shell: Option<Shell> },
@@ -92,48 +438,142 @@ enum Commands {
 #[derive(Debug, Deserialize)]
 struct Configuration {
     ponos: HashMap<String, PonoDefinition>,
+    /// Named lists of ponos to select them with `@group` or `--tag`
+    groups: Option<HashMap<String, Vec<String>>>,
+    /// Link every pono with a path relative to the target directory, so the
+    /// links survive moving the whole tree
+    #[serde(default)]
+    relative: bool,
+    /// Hooks run once per command, around all the ponos
+    hooks: Option<GlobalHooks>,
+    /// Expanded path of the file the configuration was loaded from
+    #[serde(skip)]
+    path: String,
 }
 
-fn suggest_ponos() -> Vec<PossibleValue> {
+/// Accepts any value while suggesting the given ones on completion, so that
+/// patterns and unknown ponos reach pono and get a proper error
+#[derive(Clone)]
+struct Suggestions(fn() -> Vec<PossibleValue>);
+
+impl TypedValueParser for Suggestions {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<String, clap::Error> {
+        StringValueParser::new().parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new((self.0)().into_iter()))
+    }
+}
+
+/// Loads the config given in the command line to suggest values on completion
+fn completion_config() -> Result<Configuration, PonoError> {
     let config_path = env::args()
         .collect::<Vec<String>>()
         .into_iter()
         .find(|s| s.ends_with(".toml"));
-    let config = load_config(config_path);
-    match config {
-        Ok(cfg) => cfg
-            .ponos
-            .keys()
-            .map(|s| PossibleValue::new(Into::<String>::into(s)))
+    load_config(config_path)
+}
+
+fn suggest_ponos() -> Vec<PossibleValue> {
+    match completion_config() {
+        Ok(cfg) => {
+            let mut names: Vec<String> = cfg.ponos.keys().cloned().collect();
+            names.sort();
+            names.extend(group_names(&cfg).iter().map(|name| format!("@{}", name)));
+            names.into_iter().map(PossibleValue::new).collect()
+        }
+        Err(_) => vec![],
+    }
+}
+
+fn suggest_groups() -> Vec<PossibleValue> {
+    match completion_config() {
+        Ok(cfg) => group_names(&cfg)
+            .into_iter()
+            .map(PossibleValue::new)
             .collect(),
-        Err(_) => {
-            return vec![];
+        Err(_) => vec![],
+    }
+}
+
+fn suggest_variants() -> Vec<PossibleValue> {
+    match completion_config() {
+        Ok(cfg) => {
+            let mut names: Vec<&String> = cfg
+                .ponos
+                .values()
+                .filter_map(|pono| pono.variants.as_ref())
+                .flat_map(|variants| variants.keys())
+                .collect();
+            names.sort();
+            names.dedup();
+            names
+                .into_iter()
+                .map(|s| PossibleValue::new(Into::<String>::into(s)))
+                .collect()
         }
+        Err(_) => vec![],
     }
 }
 
 fn main() {
     let args = Args::parse();
 
-    // Validate all ponos before performing filesystem operations
+    // Validate all ponos before performing filesystem operations.
+    // On dry runs the errors are reported along with the plan instead.
+    let mut has_invalid = false;
     match &args.command {
-        Commands::Enable { ponos } | Commands::Disable { ponos } => {
+        Commands::Enable { ponos, tags, .. } | Commands::Disable { ponos, tags, .. } => {
             let config = handle_config_error(load_config(args.config.clone()));
-            for pkg_name in ponos_to_manipulate(&config, &ponos) {
+            let selected =
+                handle_selection_error(ponos_to_manipulate(&config, &with_tags(ponos, tags)));
+            // a state pono can't read is reported when disabling
+            let state = State::load().unwrap_or_default();
+            for pkg_name in selected {
                 let pono_definition = config.ponos.get(&pkg_name).unwrap();
-                match validate_package(&pono_definition) {
+                let result = match &args.command {
+                    Commands::Enable { on_conflict, .. } => {
+                        validate_package(pono_definition, pono_definition.on_conflict(*on_conflict))
+                    }
+                    Commands::Disable { force: false, .. } => {
+                        check_ownership(pono_definition, &state, &config.path)
+                    }
+                    _ => Ok(()),
+                };
+                match result {
                     Ok(_) => (),
                     Err(PonoError::TargetAlreadyExists(err)) => {
-                        if let Commands::Enable { .. } = args.command {
-                            println_color!(RED, "Invalid ponos: {}", pkg_name);
-                            println_color!(RED, "Reason: {}", err);
-                            std::process::exit(1);
-                        }
+                        println_color!(RED, "Invalid ponos: {}", pkg_name);
+                        println_color!(RED, "Reason: {}", err);
+                        print_conflict_hint(&pkg_name, pono_definition);
+                        exit_unless_dry_run(args.dry_run);
+                        has_invalid = true;
                     }
                     Err(err) => {
                         println_color!(RED, "Invalid pono: {}", pkg_name);
                         println_color!(RED, "Reason: {}", err);
-                        std::process::exit(1);
+                        match &args.command {
+                            Commands::Enable { .. } => {
+                                print_conflict_hint(&pkg_name, pono_definition)
+                            }
+                            _ => {
+                                println!("Debugging:");
+                                println!(
+                                    " - Run `pono disable {} --force` to back up the target instead",
+                                    pkg_name
+                                );
+                            }
+                        }
+                        exit_unless_dry_run(args.dry_run);
+                        has_invalid = true;
                     }
                 }
             }
@@ -142,87 +582,270 @@ fn main() {
     }
 
     match args.command {
-        Commands::Enable { ponos } => {
+        Commands::Enable {
+            ponos,
+            tags,
+            on_conflict,
+            yes,
+        } => {
             // Commands with side effects
             let config = handle_config_error(load_config(args.config));
             println!("Linking ponos");
-            for pkg_name in ponos_to_manipulate(&config, &ponos) {
+            let mut transaction = Transaction::default();
+            let mut trash = vec![];
+            let selected =
+                handle_selection_error(ponos_to_manipulate(&config, &with_tags(&ponos, &tags)));
+            add_batch_hook(&mut transaction, &config, &selected, "enable", "before-all");
+            for pkg_name in selected.clone() {
                 let pono_definition = config.ponos.get(&pkg_name).unwrap();
 
                 println!(
                     "{}  {} -> {} (linking)",
                     pkg_name, pono_definition.source, pono_definition.target
                 );
-                let src_path = path(&pono_definition.source);
-                let target_path = path(&pono_definition.target);
-
-                if let Some(hooks) = &pono_definition.hooks {
-                    if let Some(pre_enable_hook) = &hooks.pre_enable {
-                        if let Err(err) = std::process::Command::new("sh")
-                            .arg("-c")
-                            .arg(pre_enable_hook)
-                            .status()
-                        {
-                            println_color!(RED, "Failed to execute pre-enable hook: {}", err);
-                            std::process::exit(1);
-                        }
-                    }
+
+                // Errors expanding the pono were reported on validation.
+                // Links already pointing to their source are left alone.
+                let (linked, links): (Vec<PonoLink>, Vec<PonoLink>) = pono_links(pono_definition)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .partition(|link| {
+                        check_link(&link.source, &link.target, &link.source, &link.target).is_ok()
+                    });
+                for link in linked {
+                    let target = if pono_definition.is_tree() {
+                        &link.target
+                    } else {
+                        &pono_definition.target
+                    };
+                    println!("  {}: {} (already linked)", pkg_name, target);
+                }
+                let dirs: Vec<String> = pono_dirs(pono_definition)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|dir| !std::path::Path::new(dir).exists())
+                    .collect();
+                if links.is_empty() && dirs.is_empty() {
+                    continue;
                 }
 
-                match symlink(&src_path, &target_path) {
-                    Ok(_) => {
-                        println_color!(
-                            GREEN,
-                            "  {}: {} (new link)",
-                            pkg_name,
-                            pono_definition.target
-                        );
-                    }
-                    Err(err) => {
-                        println_color!(RED, "Pono link failed reason: {}", err);
-                        std::process::exit(1);
+                let source = path(&linked_source(pono_definition));
+                add_hook(
+                    &mut transaction,
+                    &config,
+                    &pkg_name,
+                    &source,
+                    "enable",
+                    "on-error",
+                );
+                add_hook(
+                    &mut transaction,
+                    &config,
+                    &pkg_name,
+                    &source,
+                    "enable",
+                    "pre",
+                );
+
+                for dir in dirs {
+                    transaction.add(&pkg_name, Operation::CreateDir { path: dir });
+                }
+                let on_conflict = pono_definition.on_conflict(on_conflict);
+                for link in links {
+                    plan_link(
+                        &mut transaction,
+                        &pkg_name,
+                        link,
+                        on_conflict,
+                        pono_definition.relative(&config),
+                        yes || args.dry_run,
+                        &mut trash,
+                    );
+                }
+                add_hook(
+                    &mut transaction,
+                    &config,
+                    &pkg_name,
+                    &source,
+                    "enable",
+                    "post",
+                );
+            }
+            add_batch_hook(&mut transaction, &config, &selected, "enable", "after-all");
+
+            if args.dry_run {
+                print_plan(&transaction, has_invalid);
+                return;
+            }
+
+            commit_transaction(&config, transaction, |step| match &step.operation {
+                Operation::Link { target, .. } | Operation::Relink { target, .. } => {
+                    let pono_definition = &config.ponos[&step.pono];
+                    let target = if pono_definition.is_tree() {
+                        target
+                    } else {
+                        &pono_definition.target
+                    };
+                    if let Operation::Relink { .. } = step.operation {
+                        println_color!(GREEN, "  {}: {} (fixed link)", step.pono, target);
+                    } else {
+                        println_color!(GREEN, "  {}: {} (new link)", step.pono, target);
                     }
-                };
+                }
+                Operation::Rename { from, to } if trash.contains(to) => {
+                    println_color!(GREEN, "  {}: {} (overwritten)", step.pono, from);
+                }
+                Operation::Rename { to, .. } if backup::is_backup(to) => {
+                    println_color!(GREEN, "  {}: {} (backup)", step.pono, to);
+                }
+                Operation::Rename { from, to } => {
+                    println_color!(GREEN, "  {}: {} -> {} (adopted)", step.pono, from, to);
+                }
+                _ => (),
+            });
+
+            for path in trash {
+                if let Err(err) = backup::purge(&path) {
+                    println_color!(RED, "Failed to remove {}: {}", path, err);
+                }
             }
         }
-        Commands::Disable { ponos } => {
+        Commands::Disable { ponos, tags, force } => {
             let config = handle_config_error(load_config(args.config));
-            for pkg_name in ponos_to_manipulate(&config, &ponos) {
+            let mut transaction = Transaction::default();
+            let selected =
+                handle_selection_error(ponos_to_manipulate(&config, &with_tags(&ponos, &tags)));
+            let state = State::load().unwrap_or_else(|err| {
+                println_color!(RED, "Failed to read the state: {}", err);
+                State::default()
+            });
+            add_batch_hook(
+                &mut transaction,
+                &config,
+                &selected,
+                "disable",
+                "before-all",
+            );
+            for pkg_name in selected.clone() {
                 let pono_definition = config.ponos.get(&pkg_name).unwrap();
-                let target_path = path(&pono_definition.target);
-                if let Some(hooks) = &pono_definition.hooks {
-                    if let Some(pre_disable_hook) = &hooks.pre_disable {
-                        if let Err(err) = std::process::Command::new("sh")
-                            .arg("-c")
-                            .arg(pre_disable_hook)
-                            .status()
-                        {
-                            println_color!(RED, "Failed to execute pre-disable hook: {}", err);
-                            std::process::exit(1);
+                let source = path(&linked_source(pono_definition));
+                add_hook(
+                    &mut transaction,
+                    &config,
+                    &pkg_name,
+                    &source,
+                    "disable",
+                    "on-error",
+                );
+                add_hook(
+                    &mut transaction,
+                    &config,
+                    &pkg_name,
+                    &source,
+                    "disable",
+                    "pre",
+                );
+                for link in pono_links(pono_definition).unwrap_or_default() {
+                    match owned_link(&link.source, &link.target, &link.source, &link.target) {
+                        Ok(_) => transaction.add(
+                            &pkg_name,
+                            Operation::Unlink {
+                                target: link.target,
+                            },
+                        ),
+                        // Files added to a tree source after enabling it were never linked
+                        Err(PonoError::NotFound(_)) if pono_definition.is_tree() => (),
+                        Err(PonoError::NotFound(_)) => {
+                            println!("  {}: {} (not linked)", pkg_name, pono_definition.target);
                         }
+                        // linked by pono to a source the config doesn't use anymore
+                        Err(_) if state.made_link(&config.path, &link.target) => transaction.add(
+                            &pkg_name,
+                            Operation::Unlink {
+                                target: link.target,
+                            },
+                        ),
+                        Err(_) if force => transaction.add(
+                            &pkg_name,
+                            Operation::Rename {
+                                from: link.target.clone(),
+                                to: backup::next_backup_path(&link.target),
+                            },
+                        ),
+                        // Targets pono doesn't own were reported on validation
+                        Err(_) => (),
                     }
                 }
-                match std::fs::remove_file(&target_path) {
-                    Ok(_) => println!("Unlinked pono: {}", pkg_name),
-                    Err(err) => {
-                        println_color!(RED, "Failed to unlink pono");
-                        println_color!(RED, " {}: {}", pkg_name, target_path);
-                        println_color!(RED, " Reason: {}", err);
-                        std::process::exit(1);
+                add_hook(
+                    &mut transaction,
+                    &config,
+                    &pkg_name,
+                    &source,
+                    "disable",
+                    "post",
+                );
+            }
+            add_batch_hook(&mut transaction, &config, &selected, "disable", "after-all");
+
+            if args.dry_run {
+                print_plan(&transaction, has_invalid);
+                return;
+            }
+
+            commit_transaction(&config, transaction, |step| match &step.operation {
+                Operation::Unlink { target } => {
+                    if config.ponos[&step.pono].is_tree() {
+                        println!("Unlinked pono: {} ({})", step.pono, target);
+                    } else {
+                        println!("Unlinked pono: {}", step.pono);
                     }
                 }
-            }
+                Operation::Rename { from, to } => {
+                    println_color!(GREEN, "  {}: {} -> {} (backup)", step.pono, from, to);
+                }
+                _ => (),
+            });
         }
 
         // Commands without side effects
-        Commands::Status { ponos } => {
+        Commands::Status {
+            ponos,
+            tags,
+            format,
+        } => {
+            let config = handle_config_error(load_config(args.config));
+            let selected =
+                handle_selection_error(ponos_to_manipulate(&config, &with_tags(&ponos, &tags)));
+
+            if format != Format::Text {
+                let reports: Vec<PonoReport> = selected
+                    .iter()
+                    .map(|name| PonoReport::new(name, &config.ponos[name]))
+                    .collect();
+                print_reports(&reports, format);
+                if !reports.iter().all(|report| report.is_linked()) {
+                    std::process::exit(1);
+                }
+                return;
+            }
+
             println!("Status:");
             let mut has_error = false;
-            let config = handle_config_error(load_config(args.config));
-            for pkg_name in ponos_to_manipulate(&config, &ponos) {
+            for pkg_name in selected {
                 let pono_definition = config.ponos.get(&pkg_name).unwrap();
 
-                match check_package(&pono_definition) {
+                if let Some(variant) = active_variant(pono_definition) {
+                    println_color!(
+                        GREEN,
+                        "  {} {} (linked, variant {})",
+                        pkg_name,
+                        pono_definition.target,
+                        variant
+                    );
+                    continue;
+                }
+
+                match check_package(pono_definition) {
                     Ok(_) => {
                         println_color!(GREEN, "  {} {} (linked)", pkg_name, pono_definition.target);
                     }
@@ -232,57 +855,77 @@ fn main() {
                         has_error = true;
                     }
                 };
+                print_patterns_report(pono_definition);
+            }
+
+            // links of removed ponos only show up when checking the whole config
+            if ponos.is_none() && tags.is_empty() {
+                print_stale_links(&config);
             }
 
             if has_error {
                 std::process::exit(1);
             }
         }
-        Commands::List => {
+        Commands::List { format } => {
             let config = handle_config_error(load_config(args.config));
+            let selected = handle_selection_error(ponos_to_manipulate(&config, &None));
+
+            if format != Format::Text {
+                let reports: Vec<PonoReport> = selected
+                    .iter()
+                    .map(|name| PonoReport::new(name, &config.ponos[name]))
+                    .collect();
+                print_reports(&reports, format);
+                return;
+            }
+
             println!("Ponos:");
-            for package in ponos_to_manipulate(&config, &None) {
+            for package in selected {
                 let pono_definition = config.ponos.get(&package).unwrap();
                 println!("  {}: {}", package, pono_definition.source);
+                for (name, variant) in pono_definition.variants.iter().flatten() {
+                    let default = if variant.default { " (default)" } else { "" };
+                    println!("    variant {}: {}{}", name, variant.source, default);
+                }
+                print_patterns_report(pono_definition);
             }
         }
-        Commands::Toggle { pono } => {
+        Commands::Toggle { pono, variant } => {
             let config = handle_config_error(load_config(args.config));
-            let pono_info = match config.ponos.get(&pono) {
-                Some(pono) => pono,
-                _ => {
-                    println!("{}Pono not found {} in ponos list", RED, pono);
-                    println!("Debugging:");
-                    println!(" - Run `pono list` to see the available ponos");
-                    std::process::exit(1);
-                }
-            };
-
-            let src_path = path(&pono_info.source);
-            let target_path = path(&pono_info.target);
-
-            // backup current target {pono}.bak
-            let bak_path = format!("{}.bak", target_path);
-            std::fs::remove_file(&bak_path).ok();
-            match std::fs::rename(&target_path, &bak_path) {
-                Ok(_) => {
-                    println!("{}  {}: {} (backup)", GREEN, pono, bak_path)
-                }
-                Err(err) => {
-                    println!("{}Pono backup failed reason: {}", RED, err);
-                    std::process::exit(1);
-                }
-            };
-
-            match symlink(&src_path, &target_path) {
-                Ok(_) => {
-                    println!("{}  {}: {} (new link)", GREEN, pono, target_path)
-                }
-                Err(err) => {
-                    println!("{}Pono link failed reason: {}", RED, err);
-                    std::process::exit(1);
-                }
-            };
+            commands::toggle::run(&config, &pono, variant, args.dry_run);
+        }
+        Commands::Restore { pono } => {
+            let config = handle_config_error(load_config(args.config));
+            commands::restore::run(&config, &pono, args.dry_run);
+        }
+        Commands::Adopt { pono, yes } => {
+            let config = handle_config_error(load_config(args.config));
+            commands::adopt::run(&config, &pono, yes, args.dry_run);
+        }
+        Commands::Sync { prune, yes } => {
+            let config = handle_config_error(load_config(args.config));
+            commands::sync::run(&config, prune, yes, args.dry_run);
+        }
+        Commands::History => {
+            let config = handle_config_error(load_config(args.config));
+            commands::history::run(&config);
+        }
+        Commands::Undo { n } => {
+            let config = handle_config_error(load_config(args.config));
+            commands::undo::run(&config, n, args.dry_run);
+        }
+        Commands::Init { template, force } => {
+            let config_path = args.config.unwrap_or("pono.toml".to_string());
+            commands::init::run(&config_path, template, force, args.dry_run);
+        }
+        Commands::Scan {
+            dir,
+            target_root,
+            write,
+        } => {
+            let config_path = args.config.unwrap_or("pono.toml".to_string());
+            commands::scan::run(&dir, &target_root, &config_path, write, args.dry_run);
         }
         Commands::Completions { shell } => {
             let current_shell = shell.unwrap_or_else(|| {
@@ -297,11 +940,12 @@ fn main() {
                     None
                 };
 
-                if env_shell.is_none() {
-                    println!("Pono doesn't support the current shell {}", shell_in_env);
-                    std::process::exit(1);
-                } else {
-                    env_shell.unwrap()
+                match env_shell {
+                    Some(shell) => shell,
+                    None => {
+                        println!("Pono doesn't support the current shell {}", shell_in_env);
+                        std::process::exit(1);
+                    }
                 }
             });
 
@@ -321,16 +965,73 @@ enum PonoError {
     NotSymlink(String),
     TargetAlreadyExists(String),
     LinkMismatch(String),
+    /// The symlink exists but what it points to does not
+    Dangling(String),
+    /// A hook exited with a non-zero code, none when killed by a signal
+    HookFailed {
+        event: String,
+        code: Option<i32>,
+        stderr: String,
+    },
+    /// A hook ran for longer than its timeout and was killed
+    HookTimeout {
+        event: String,
+        timeout: std::time::Duration,
+    },
     Unhandled(String),
 }
+impl PonoError {
+    /// Short name of the error, shown between parentheses in messages
+    fn kind(&self) -> &'static str {
+        match self {
+            PonoError::ConfigError(..) => "config-error",
+            PonoError::NotFound(_) => "not-found",
+            PonoError::NotSymlink(_) => "not-symlink",
+            PonoError::LinkMismatch(_) => "link-mismatch",
+            PonoError::Dangling(_) => "dangling",
+            PonoError::HookFailed { .. } => "hook-failed",
+            PonoError::HookTimeout { .. } => "hook-timeout",
+            PonoError::TargetAlreadyExists(_) => "not-available",
+            PonoError::Unhandled(_) => "unhandled",
+        }
+    }
+}
 impl Display for PonoError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            PonoError::ConfigError(msg, _) => write!(f, "(config-error) {}", msg),
-            PonoError::NotFound(msg) => write!(f, "(not-found) {}", msg),
-            PonoError::NotSymlink(msg) => write!(f, "(non-pono_definition) {}", msg),
-            PonoError::LinkMismatch(msg) => write!(f, "(link-mismatch) {}", msg),
-            PonoError::TargetAlreadyExists(msg) => write!(f, "(not-available) {}", msg),
+            PonoError::ConfigError(msg, _)
+            | PonoError::NotFound(msg)
+            | PonoError::NotSymlink(msg)
+            | PonoError::LinkMismatch(msg)
+            | PonoError::Dangling(msg)
+            | PonoError::TargetAlreadyExists(msg) => write!(f, "({}) {}", self.kind(), msg),
+            PonoError::HookFailed {
+                event,
+                code,
+                stderr,
+            } => {
+                match code {
+                    Some(code) => write!(
+                        f,
+                        "({}) {} hook exited with code {}",
+                        self.kind(),
+                        event,
+                        code
+                    )?,
+                    None => write!(f, "({}) {} hook was killed by a signal", self.kind(), event)?,
+                }
+                match stderr.trim() {
+                    "" => Ok(()),
+                    stderr => write!(f, ": {}", stderr),
+                }
+            }
+            PonoError::HookTimeout { event, timeout } => write!(
+                f,
+                "({}) {} hook was killed after {:?}",
+                self.kind(),
+                event,
+                timeout
+            ),
             PonoError::Unhandled(msg) => write!(f, "{}", msg),
         }
     }
@@ -338,7 +1039,7 @@ impl Display for PonoError {
 
 fn handle_config_error(res: Result<Configuration, PonoError>) -> Configuration {
     match res {
-        Ok(config) => return config,
+        Ok(config) => config,
         Err(PonoError::ConfigError(err, config)) => {
             println!("Failed to read the {} file", config);
             print!("{}", RED);
@@ -351,12 +1052,375 @@ fn handle_config_error(res: Result<Configuration, PonoError>) -> Configuration {
             #[allow(unreachable_code)]
             Configuration {
                 ponos: HashMap::new(),
+                groups: None,
+                relative: false,
+                hooks: None,
+                path: String::new(),
             }
         }
         _ => todo!("This shouldn't happen. Open an issue on GitHub"),
     }
 }
 
+/// Suggests how to resolve an existing target: `pono adopt` for a file or
+/// directory that could become the pono source, `--on-conflict` for a link
+/// pointing somewhere else
+fn print_conflict_hint(name: &str, package: &PonoDefinition) {
+    if package.is_tree() {
+        return;
+    }
+    match std::fs::symlink_metadata(path(&package.target)) {
+        Ok(metadata) if metadata.is_symlink() => {
+            println!("Debugging:");
+            println!(
+                " - Run `pono enable {} --on-conflict backup` to back up the link and link the source instead",
+                name
+            );
+        }
+        Ok(_) => {
+            println!("Debugging:");
+            println!(
+                " - Run `pono adopt {}` to move the target into the pono source",
+                name
+            );
+        }
+        Err(_) => (),
+    }
+}
+
+/// Adds the steps linking the source in place of the target, resolving an
+/// existing target with the conflict policy. Overwritten targets are moved to
+/// `trash`, to remove once the transaction is committed. Adopting a target
+/// over an existing source asks first, unless `yes`.
+fn plan_link(
+    transaction: &mut Transaction,
+    pono: &str,
+    link: PonoLink,
+    on_conflict: OnConflict,
+    relative: bool,
+    yes: bool,
+    trash: &mut Vec<String>,
+) {
+    let dangling = matches!(
+        check_link(&link.source, &link.target, &link.source, &link.target),
+        Err(PonoError::Dangling(_))
+    );
+    if dangling {
+        // a broken link has nothing to keep, point it to the source
+        transaction.add(
+            pono,
+            Operation::Relink {
+                source: link_source(&link.source, &link.target, relative),
+                target: link.target,
+            },
+        );
+        return;
+    }
+    let exists = std::fs::symlink_metadata(&link.target).is_ok();
+    match on_conflict {
+        // Failing conflicts were reported on validation
+        _ if !exists => (),
+        OnConflict::Fail => (),
+        OnConflict::Backup => {
+            transaction.add(
+                pono,
+                Operation::Rename {
+                    from: link.target.clone(),
+                    to: backup::next_backup_path(&link.target),
+                },
+            );
+        }
+        OnConflict::Overwrite => {
+            let to = backup::next_trash_path(&link.target);
+            trash.push(to.clone());
+            transaction.add(
+                pono,
+                Operation::Rename {
+                    from: link.target.clone(),
+                    to,
+                },
+            );
+        }
+        OnConflict::Skip => {
+            println!("  {}: {} (skipped, target exists)", pono, link.target);
+            return;
+        }
+        OnConflict::Adopt => {
+            if std::path::Path::new(&link.source).exists()
+                && !commands::adopt::confirm_replace(&link.source, &link.target, yes)
+            {
+                println_color!(RED, "Pono {} was not adopted", pono);
+                println!("Debugging:");
+                println!(" - Run with `--yes` to replace the source without asking");
+                std::process::exit(1);
+            }
+            commands::adopt::plan(transaction, pono, &link.source, &link.target, relative);
+            return;
+        }
+    }
+    transaction.add(
+        pono,
+        Operation::Link {
+            source: link_source(&link.source, &link.target, relative),
+            target: link.target,
+        },
+    );
+}
+
+/// Adds the pono hook run at `stage` (`pre`, `post` or `on-error`) of the
+/// action to the transaction, when defined. `source` is the expanded source
+/// the action links, e.g. the variant switched to. The `on-error` hook only
+/// runs if a step of the pono fails.
+fn add_hook(
+    transaction: &mut Transaction,
+    config: &Configuration,
+    pono: &str,
+    source: &str,
+    action: &str,
+    stage: &str,
+) {
+    let event = match stage {
+        "on-error" => stage.to_string(),
+        _ => format!("{}-{}", stage, action),
+    };
+    let package = &config.ponos[pono];
+    let Some(hook) = package.hooks.as_ref().and_then(|hooks| hooks.hook(&event)) else {
+        return;
+    };
+
+    let pono_env = BTreeMap::from([
+        ("PONO_NAME".to_string(), pono.to_string()),
+        ("PONO_SOURCE".to_string(), source.to_string()),
+        ("PONO_TARGET".to_string(), path(&package.target)),
+        ("PONO_ACTION".to_string(), action.to_string()),
+        ("PONO_CONFIG".to_string(), config.path.clone()),
+    ]);
+    for hook_run in hook_runs(hook, &event, &pono_env) {
+        if stage == "on-error" {
+            transaction.add_error_hook(pono, Operation::Hook(hook_run));
+        } else {
+            transaction.add(pono, Operation::Hook(hook_run));
+        }
+    }
+}
+
+/// Adds the top-level hook run once at `stage` (`before-all` or `after-all`)
+/// of the action to the transaction, when defined. `ponos` are the ponos the
+/// command changes.
+fn add_batch_hook(
+    transaction: &mut Transaction,
+    config: &Configuration,
+    ponos: &[String],
+    action: &str,
+    stage: &str,
+) {
+    let Some(hook) = config.hooks.as_ref().and_then(|h| h.hook(action, stage)) else {
+        return;
+    };
+
+    let env = BTreeMap::from([
+        ("PONO_NAMES".to_string(), ponos.join(" ")),
+        ("PONO_ACTION".to_string(), action.to_string()),
+        ("PONO_CONFIG".to_string(), config.path.clone()),
+    ]);
+    for hook_run in hook_runs(hook, stage, &env) {
+        transaction.add(ALL_PONOS, Operation::Hook(hook_run));
+    }
+}
+
+/// The commands of a hook, ready to run with the `PONO_*` variables in `env`
+fn hook_runs(hook: &Hook, event: &str, env: &BTreeMap<String, String>) -> Vec<HookRun> {
+    hook.commands()
+        .iter()
+        .map(|command| match command {
+            HookCommand::Command(script) => HookRun {
+                event: event.to_string(),
+                program: Program::Script {
+                    shell: "sh".to_string(),
+                    script: script.clone(),
+                },
+                allow_failure: false,
+                cwd: None,
+                env: env.clone(),
+                timeout: None,
+                quiet: false,
+            },
+            HookCommand::Table(table) => {
+                let env: BTreeMap<String, String> =
+                    env.clone().into_iter().chain(table.env.clone()).collect();
+                HookRun {
+                    event: event.to_string(),
+                    program: match &table.run {
+                        HookRunField::Command(script) => Program::Script {
+                            shell: table.shell.clone().unwrap_or("sh".to_string()),
+                            script: script.clone(),
+                        },
+                        HookRunField::Args(args) => {
+                            Program::Args(args.iter().map(|arg| expand_arg(arg, &env)).collect())
+                        }
+                    },
+                    allow_failure: table.allow_failure,
+                    cwd: table.cwd.as_deref().map(path),
+                    env,
+                    timeout: table.timeout.map(|timeout| timeout.0),
+                    quiet: table.quiet,
+                }
+            }
+        })
+        .collect()
+}
+
+/// Expands `~` and `$VAR` in an argument run without a shell, like a shell
+/// would. The variables of the hook come before the ones pono runs with and
+/// unknown variables are left as they are.
+fn expand_arg(arg: &str, hook_env: &BTreeMap<String, String>) -> String {
+    shellexpand::full_with_context(
+        arg,
+        || env::var("HOME").ok(),
+        |var| {
+            Ok::<_, std::convert::Infallible>(
+                hook_env.get(var).cloned().or_else(|| env::var(var).ok()),
+            )
+        },
+    )
+    .map(|arg| arg.into_owned())
+    .unwrap_or_else(|_| arg.to_string())
+}
+
+/// Exits with an error unless running with `--dry-run`, in which case the
+/// error is reported along with the plan.
+fn exit_unless_dry_run(dry_run: bool) {
+    if !dry_run {
+        std::process::exit(1);
+    }
+}
+
+/// Prints the operations of a transaction without applying them. Exits with
+/// an error when the ponos have problems a real run would fail on.
+fn print_plan(transaction: &Transaction, has_invalid: bool) {
+    println!("Dry run (nothing was changed):");
+    for step in transaction.steps() {
+        println!("  {}: {}", step.pono, step.operation);
+    }
+    for step in transaction.error_hooks() {
+        println!("  {}: {} (on error)", step.pono, step.operation);
+    }
+
+    if has_invalid {
+        println_color!(RED, "Dry run found invalid ponos, a real run would fail");
+        std::process::exit(1);
+    }
+}
+
+/// Commits the transaction or, when a step fails, reports what was rolled back
+/// and exits. `on_applied` is called for every step successfully applied.
+/// Ponos stopped by their `pre-` hooks are reported after the others are done.
+/// The applied steps are recorded in the state, as are the steps a failure
+/// couldn't roll back.
+fn commit_transaction<F: FnMut(&Step)>(
+    config: &Configuration,
+    transaction: Transaction,
+    mut on_applied: F,
+) {
+    let mut applied = vec![];
+    let undoes = transaction.undoes().to_vec();
+    let result = transaction.commit(|step| {
+        on_applied(step);
+        applied.push(step.clone());
+    });
+    match &result {
+        Ok(_) => record_changes(config, &applied, &undoes, false),
+        // the steps that couldn't be reverted are still in place
+        Err(failure) if !failure.rollback_errors.is_empty() => {
+            let left: Vec<Step> = failure
+                .rollback_errors
+                .iter()
+                .rev()
+                .map(|(step, _)| step.clone())
+                .collect();
+            record_changes(config, &left, &[], true);
+        }
+        Err(_) => (),
+    }
+    let failure = match result {
+        Ok(stopped) if stopped.is_empty() => return,
+        Ok(stopped) => {
+            // the other ponos were changed, only these were left untouched
+            for (step, err) in stopped {
+                println_color!(RED, "  {}: {} (stopped)", step.pono, step.operation);
+                println_color!(RED, "  Reason: {}", err);
+            }
+            std::process::exit(1);
+        }
+        Err(failure) => failure,
+    };
+    println_color!(RED, "Pono link failed reason: {}", failure.error);
+    println_color!(RED, "  {}: {}", failure.step.pono, failure.step.operation);
+    for step in failure.rolled_back {
+        println_color!(RED, "  {}: {} (rolled back)", step.pono, step.operation);
+    }
+    for (step, err) in failure.rollback_errors {
+        println_color!(RED, "  {}: {} (rollback failed)", step.pono, step.operation);
+        println_color!(RED, "  Reason: {}", err);
+    }
+    for (step, err) in failure.error_hooks {
+        match err {
+            None => {
+                println_color!(RED, "  {}: {} (ran on error)", step.pono, step.operation);
+            }
+            Some(err) => {
+                println_color!(RED, "  {}: {} (failed)", step.pono, step.operation);
+                println_color!(RED, "  Reason: {}", err);
+            }
+        }
+    }
+    std::process::exit(1);
+}
+
+/// Reports the links pono made for ponos since removed from the config
+fn print_stale_links(config: &Configuration) {
+    let state = match State::load() {
+        Ok(state) => state,
+        Err(err) => {
+            println_color!(RED, "Failed to read the state: {}", err);
+            return;
+        }
+    };
+    let stale = commands::sync::stale_links(config, &state);
+    if stale.is_empty() {
+        return;
+    }
+
+    println!("Removed from the config:");
+    for link in stale {
+        println_color!(
+            RED,
+            "  {} {} -> {} (stale link)",
+            link.pono,
+            link.target,
+            link.source
+        );
+    }
+    println!("Debugging:");
+    println!(" - Run `pono sync --prune` to remove them");
+}
+
+/// Records the applied steps in the state and the history, `failed` when
+/// they are what a failed transaction couldn't roll back. A state pono can't
+/// read is left as it is.
+fn record_changes(config: &Configuration, applied: &[Step], undoes: &[u64], failed: bool) {
+    let result = State::load().and_then(|mut state| {
+        state.record(&config.path, applied);
+        state.save()
+    });
+    if let Err(err) = result {
+        println_color!(RED, "Failed to update the state: {}", err);
+    }
+    if let Err(err) = history::append(&config.path, applied, undoes, failed) {
+        println_color!(RED, "Failed to update the history: {}", err);
+    }
+}
+
 fn load_config(config_arg: Option<String>) -> Result<Configuration, PonoError> {
     let config = config_arg.unwrap_or("pono.toml".to_string());
     let config_path = path(&config);
@@ -374,60 +1438,387 @@ fn load_config(config_arg: Option<String>) -> Result<Configuration, PonoError> {
         }
     };
 
-    Ok(maybe_config.unwrap())
+    let mut pono_config = maybe_config.unwrap();
+    pono_config.path = config_path;
+    for (name, pono) in pono_config.ponos.iter_mut() {
+        let Some(variants) = &pono.variants else {
+            if pono.source.is_empty() {
+                return Err(PonoError::ConfigError(
+                    format!("Pono {} is missing the field `source`", name),
+                    config,
+                ));
+            }
+            continue;
+        };
+
+        if !pono.source.is_empty() {
+            return Err(PonoError::ConfigError(
+                format!("Pono {} has both `source` and `variants`", name),
+                config,
+            ));
+        }
+        let defaults: Vec<&Variant> = variants.values().filter(|v| v.default).collect();
+        if defaults.len() != 1 {
+            return Err(PonoError::ConfigError(
+                format!("Pono {} must have exactly one default variant", name),
+                config,
+            ));
+        }
+        pono.source = defaults[0].source.clone();
+    }
+
+    for (group, members) in pono_config.groups.iter().flatten() {
+        if let Some(unknown) = members.iter().find(|m| !pono_config.ponos.contains_key(*m)) {
+            return Err(PonoError::ConfigError(
+                format!("Group {} has an unknown pono {}", group, unknown),
+                config,
+            ));
+        }
+    }
+
+    Ok(pono_config)
+}
+
+/// The variant the pono target is currently linked to
+fn active_variant(package: &PonoDefinition) -> Option<String> {
+    let sln_path = path(&package.target);
+    package
+        .variants
+        .iter()
+        .flatten()
+        .find(|(_, variant)| {
+            check_link(
+                &path(&variant.source),
+                &sln_path,
+                &variant.source,
+                &package.target,
+            )
+            .is_ok()
+        })
+        .map(|(name, _)| name.clone())
+}
+
+/// The source the pono target is linked to: the active variant, or the
+/// default source when no variant is linked
+fn linked_source(package: &PonoDefinition) -> String {
+    active_variant(package)
+        .and_then(|name| Some(package.variants.as_ref()?[&name].source.clone()))
+        .unwrap_or_else(|| package.source.clone())
+}
+
+/// The variant after the active one, or the default variant when the pono
+/// isn't linked to any of them
+fn next_variant(package: &PonoDefinition) -> Option<String> {
+    let variants = package.variants.as_ref()?;
+    let names: Vec<&String> = variants.keys().collect();
+    match active_variant(package) {
+        Some(active) => {
+            let index = names.iter().position(|name| **name == active)?;
+            Some(names[(index + 1) % names.len()].clone())
+        }
+        None => variants
+            .iter()
+            .find(|(_, variant)| variant.default)
+            .map(|(name, _)| name.clone()),
+    }
+}
+
+/// A symlink to create for a pono, with expanded paths
+struct PonoLink {
+    source: String,
+    target: String,
+}
+
+/// Expands a pono into the symlinks it is made of. Ponos in `link` mode are
+/// a single link, to the active variant for ponos with variants, while `tree`
+/// ponos have one link per file in the source.
+fn pono_links(package: &PonoDefinition) -> Result<Vec<PonoLink>, PonoError> {
+    let src_path = path(&package.source);
+    let sln_path = path(&package.target);
+    if !package.is_tree() {
+        return Ok(vec![PonoLink {
+            source: path(&linked_source(package)),
+            target: sln_path,
+        }]);
+    }
+
+    Ok(walk_package(package)?
+        .entries
+        .into_iter()
+        .filter(|entry| !entry.is_dir)
+        .map(|entry| PonoLink {
+            source: join(&src_path, &entry.path),
+            target: join(&sln_path, &entry.path),
+        })
+        .collect())
 }
 
-fn validate_package(package: &PonoDefinition) -> Result<(), PonoError> {
+/// Directories a `tree` pono needs in its target to hold its links, parents
+/// first: the missing parents of the target, the target and the directories
+/// of the source inside it
+fn pono_dirs(package: &PonoDefinition) -> Result<Vec<String>, PonoError> {
+    if !package.is_tree() {
+        return Ok(vec![]);
+    }
+
     let sln_path = path(&package.target);
+    let mut dirs = std::collections::BTreeSet::new();
+    for entry in walk_package(package)?.entries {
+        if !entry.is_dir {
+            dirs.extend(entry.path.ancestors().skip(1).map(|dir| dir.to_path_buf()));
+        }
+    }
+    let mut parents: Vec<String> = std::path::Path::new(&sln_path)
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        .map(|dir| dir.to_string_lossy().to_string())
+        .collect();
+    parents.reverse();
+    // The empty path is the target itself
+    dirs.remove(&PathBuf::new());
+    Ok(parents
+        .into_iter()
+        .chain(std::iter::once(sln_path.clone()))
+        .chain(dirs.iter().map(|dir| join(&sln_path, dir)))
+        .collect())
+}
+
+fn walk_package(package: &PonoDefinition) -> Result<tree::Walk, PonoError> {
     let src_path = path(&package.source);
+    let filter = tree::Filter::new(
+        package.include.as_deref().unwrap_or_default(),
+        package.exclude.as_deref().unwrap_or_default(),
+    )
+    .map_err(|err| PonoError::Unhandled(format!("Invalid pattern: {}", err)))?;
+
+    tree::walk(std::path::Path::new(&src_path), &filter).map_err(|err| {
+        PonoError::Unhandled(format!("Failed to read tree source {}: {}", src_path, err))
+    })
+}
+
+/// Prints which files the include and exclude patterns of a pono matched
+fn print_patterns_report(package: &PonoDefinition) {
+    if package.include.is_none() && package.exclude.is_none() {
+        return;
+    }
+
+    let walk = match walk_package(package) {
+        Ok(walk) => walk,
+        Err(err) => {
+            println_color!(RED, "    Reason: {}", err);
+            return;
+        }
+    };
+
+    for pattern in package.include.iter().flatten() {
+        let files: Vec<String> = walk
+            .entries
+            .iter()
+            .filter(|entry| entry.included_by.as_ref() == Some(pattern))
+            .map(|entry| entry.path.display().to_string())
+            .collect();
+        println!("    include {}: {}", pattern, files.join(", "));
+    }
+    for pattern in package.exclude.iter().flatten() {
+        let files: Vec<String> = walk
+            .skipped
+            .iter()
+            .filter(|skipped| skipped.excluded_by.as_ref() == Some(pattern))
+            .map(|skipped| skipped.path.display().to_string())
+            .collect();
+        println!("    exclude {}: {}", pattern, files.join(", "));
+    }
+    let not_included: Vec<String> = walk
+        .skipped
+        .iter()
+        .filter(|skipped| skipped.excluded_by.is_none())
+        .map(|skipped| skipped.path.display().to_string())
+        .collect();
+    if !not_included.is_empty() {
+        println!("    not included: {}", not_included.join(", "));
+    }
+}
+
+/// Checks the pono can be linked. Existing targets are only an error when
+/// the conflict policy can't resolve them, a link to any variant of the pono
+/// is already linked.
+fn validate_package(package: &PonoDefinition, on_conflict: OnConflict) -> Result<(), PonoError> {
+    let source = linked_source(package);
+    let src_path = path(&source);
 
-    // check if source exists
-    if !std::path::Path::new(&src_path).exists() {
+    // check if source exists, unless it's about to be adopted from the target
+    let adopts_target = on_conflict == OnConflict::Adopt
+        && !package.is_tree()
+        && std::fs::symlink_metadata(path(&package.target))
+            .is_ok_and(|metadata| !metadata.is_symlink());
+    if !std::path::Path::new(&src_path).exists() && !adopts_target {
         return Err(PonoError::NotFound(format!(
             "Pono source does not exist: {} ",
             src_path
         )));
     }
 
-    if std::path::Path::new(&sln_path).exists() {
-        let sln_metadata = match std::fs::symlink_metadata(&sln_path) {
-            Ok(metadata) => metadata,
-            Err(err) => {
-                return Err(PonoError::Unhandled(format!(
-                    "Target isn't accessible: {:?}",
-                    err
-                )))
+    if package.is_tree() {
+        if !std::path::Path::new(&src_path).is_dir() {
+            return Err(PonoError::Unhandled(format!(
+                "Tree pono source must be a directory: {}",
+                src_path
+            )));
+        }
+
+        // Target directories may already exist, but must be real directories
+        // otherwise the links would be created somewhere else
+        for dir in pono_dirs(package)? {
+            if let Ok(metadata) = std::fs::symlink_metadata(&dir) {
+                if !metadata.is_dir() {
+                    return Err(PonoError::TargetAlreadyExists(format!(
+                        "(not-available) Target path '{}' already exists and is a {}.",
+                        dir,
+                        file_type_name(&metadata)
+                    )));
+                }
+            }
+        }
+
+        for link in pono_links(package)? {
+            if !resolves_conflict(on_conflict, &link.target) {
+                validate_target(&link.source, &link.target, &link.source, &link.target)?;
             }
-        };
+        }
+        return Ok(());
+    }
 
-        if !sln_metadata.file_type().is_symlink() {
-            let type_str = if sln_metadata.is_dir() {
-                "directory"
-            } else if sln_metadata.is_file() {
-                "file"
-            } else {
-                "unknown"
-            };
+    if resolves_conflict(on_conflict, &path(&package.target)) {
+        return Ok(());
+    }
+    validate_target(&src_path, &path(&package.target), &source, &package.target)
+}
+
+/// Whether the policy handles an existing target. Only real files and
+/// directories can be adopted.
+fn resolves_conflict(on_conflict: OnConflict, sln_path: &str) -> bool {
+    match on_conflict {
+        OnConflict::Fail => false,
+        OnConflict::Adopt => {
+            std::fs::symlink_metadata(sln_path).is_ok_and(|metadata| !metadata.is_symlink())
+        }
+        OnConflict::Backup | OnConflict::Overwrite | OnConflict::Skip => true,
+    }
+}
 
-            return Err(PonoError::TargetAlreadyExists(format!(
+/// Checks nothing is in the way of the link. A link already pointing to the
+/// source is left as is and a dangling link is replaced, so enabling twice
+/// succeeds.
+fn validate_target(
+    src_path: &str,
+    sln_path: &str,
+    source_name: &str,
+    target_name: &str,
+) -> Result<(), PonoError> {
+    match check_link(src_path, sln_path, source_name, target_name) {
+        Ok(_) | Err(PonoError::NotFound(_)) | Err(PonoError::Dangling(_)) => Ok(()),
+        Err(PonoError::NotSymlink(_)) => {
+            let sln_metadata = std::fs::symlink_metadata(sln_path).map_err(|err| {
+                PonoError::Unhandled(format!("Target isn't accessible: {:?}", err))
+            })?;
+            Err(PonoError::TargetAlreadyExists(format!(
                 "(not-available) Target path '{}' already exists and is a {}.",
-                &package.target, type_str
-            )));
+                target_name,
+                file_type_name(&sln_metadata)
+            )))
         }
-        return Err(PonoError::TargetAlreadyExists(format!(
-            "(not-avaiable) Pono target already exists: {}",
-            src_path
-        )));
+        Err(PonoError::LinkMismatch(err)) => Err(PonoError::TargetAlreadyExists(format!(
+            "(mismatch) {}",
+            err
+        ))),
+        Err(err) => Err(err),
+    }
+}
+
+fn check_package(package: &PonoDefinition) -> Result<(), PonoError> {
+    if package.is_tree() {
+        for link in pono_links(package)? {
+            check_link(&link.source, &link.target, &link.source, &link.target)?;
+        }
+        return Ok(());
     }
 
+    // ponos with variants are linked to whichever variant is active
+    let source = linked_source(package);
+    check_link(
+        &path(&source),
+        &path(&package.target),
+        &source,
+        &package.target,
+    )
+}
+
+/// Checks every existing target of the pono is a link pono made to its
+/// source, or to any of its variants, or a link the state of the config
+/// records pono made, so that disabling it doesn't remove anything else
+fn check_ownership(package: &PonoDefinition, state: &State, config: &str) -> Result<(), PonoError> {
+    let links = if package.is_tree() {
+        pono_links(package)?
+            .into_iter()
+            .map(|link| {
+                (
+                    link.source.clone(),
+                    link.target.clone(),
+                    link.source,
+                    link.target,
+                )
+            })
+            .collect()
+    } else {
+        let source = linked_source(package);
+        vec![(
+            path(&source),
+            path(&package.target),
+            source,
+            package.target.clone(),
+        )]
+    };
+    for (src_path, sln_path, source_name, target_name) in links {
+        match owned_link(&src_path, &sln_path, &source_name, &target_name) {
+            // nothing to remove
+            Ok(_) | Err(PonoError::NotFound(_)) => (),
+            Err(_) if state.made_link(config, &sln_path) => (),
+            Err(err) => return Err(err),
+        }
+    }
     Ok(())
 }
 
-fn check_package(package: &PonoDefinition) -> Result<(), PonoError> {
-    let sln_path = path(&package.target);
-    let src_path = path(&package.source);
+/// Checks the link like `check_link`, but a dangling link to the source path
+/// is also owned: the source was removed after linking it
+fn owned_link(
+    src_path: &str,
+    sln_path: &str,
+    source_name: &str,
+    target_name: &str,
+) -> Result<(), PonoError> {
+    match check_link(src_path, sln_path, source_name, target_name) {
+        Err(PonoError::Dangling(err)) => {
+            let sln_path = std::path::Path::new(sln_path);
+            let points_to = std::fs::read_link(sln_path)
+                .map(|points_to| resolve(&sln_path.parent().unwrap_or(sln_path).join(points_to)));
+            match points_to {
+                Ok(points_to) if points_to == resolve(std::path::Path::new(src_path)) => Ok(()),
+                _ => Err(PonoError::Dangling(err)),
+            }
+        }
+        result => result,
+    }
+}
 
-    let sln_metadata = match std::fs::symlink_metadata(&sln_path) {
+fn check_link(
+    src_path: &str,
+    sln_path: &str,
+    source_name: &str,
+    target_name: &str,
+) -> Result<(), PonoError> {
+    let sln_metadata = match std::fs::symlink_metadata(sln_path) {
         Ok(metadata) => metadata,
         Err(err) => {
             return Err(PonoError::NotFound(format!(
@@ -438,60 +1829,132 @@ fn check_package(package: &PonoDefinition) -> Result<(), PonoError> {
     };
 
     if !sln_metadata.file_type().is_symlink() {
-        let type_str = if sln_metadata.is_dir() {
-            "directory"
-        } else if sln_metadata.is_file() {
-            "file"
-        } else {
-            "unknown"
-        };
-
         return Err(PonoError::NotSymlink(format!(
             "Target path '{}' already exists and is a {}.",
-            &package.target, type_str
-        )));
-    }
-
-    let target_path = std::fs::read_link(sln_path).expect("Failed to read link");
-
-    if !target_path.exists() {
-        return Err(PonoError::NotFound(format!(
-            "Target path does not exist: {}",
-            target_path.display()
+            target_name,
+            file_type_name(&sln_metadata)
         )));
     }
 
-    // Get the metadata of the target (source) file following the symlink
-    let target_metatada = match std::fs::metadata(&target_path) {
-        Ok(metadata) => metadata,
-        Err(err) => {
-            return Err(PonoError::Unhandled(format!(
-                "Failed to read target metadata: {}",
-                err
-            )))
-        }
+    let link_path = std::fs::read_link(sln_path)
+        .map_err(|err| PonoError::Unhandled(format!("Failed to read link: {}", err)))?;
+    // A relative link is relative to the directory holding it, not to the cwd
+    let points_to = match std::path::Path::new(sln_path).parent() {
+        Some(parent) if link_path.is_relative() => parent.join(&link_path),
+        _ => link_path.clone(),
     };
-    let src_metadata = match std::fs::metadata(src_path) {
-        Ok(metadata) => metadata,
-        Err(err) => {
-            return Err(PonoError::Unhandled(format!(
-                "Failed to read source metadata: {}",
-                err
+
+    let points_to = match std::fs::canonicalize(&points_to) {
+        Ok(points_to) => points_to,
+        Err(_) => {
+            return Err(PonoError::Dangling(format!(
+                "Target '{}' points to '{}' which does not exist",
+                target_name,
+                link_path.display()
             )))
         }
     };
+    let src_canonical = std::fs::canonicalize(src_path)
+        .map_err(|err| PonoError::Unhandled(format!("Failed to read source metadata: {}", err)))?;
 
-    // Compare inode and device numbers to check if they point to the same file
-    if target_metatada.len() == src_metadata.len() {
+    if same_file(&points_to, &src_canonical)? {
         return Ok(());
     }
 
     Err(PonoError::LinkMismatch(format!(
-        "Package link mismatch between target '{}' and source '{}'",
-        package.target, package.source
+        "Target '{}' points to '{}' instead of source '{}'",
+        target_name,
+        points_to.display(),
+        source_name
     )))
 }
 
+/// The text of a link to `src_path` made at `sln_path`: the source path or,
+/// for relative links, the source relative to the directory of the link
+fn link_source(src_path: &str, sln_path: &str, relative: bool) -> String {
+    if !relative {
+        return src_path.to_string();
+    }
+
+    let source = resolve(std::path::Path::new(src_path));
+    let sln_dir = resolve(
+        std::path::Path::new(sln_path)
+            .parent()
+            .unwrap_or(std::path::Path::new("/")),
+    );
+    let common = source
+        .components()
+        .zip(sln_dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative_path = PathBuf::new();
+    for _ in sln_dir.components().skip(common) {
+        relative_path.push("..");
+    }
+    relative_path.extend(source.components().skip(common));
+    if relative_path.as_os_str().is_empty() {
+        return ".".to_string();
+    }
+    relative_path.to_string_lossy().to_string()
+}
+
+/// The absolute path with the links of its existing part followed and `..`
+/// resolved, so paths that don't exist yet compare with canonical ones
+fn resolve(path: &std::path::Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let existing = path
+        .ancestors()
+        .find_map(|ancestor| Some((ancestor, std::fs::canonicalize(ancestor).ok()?)));
+    let Some((ancestor, mut resolved)) = existing else {
+        return path;
+    };
+    for component in path.strip_prefix(ancestor).unwrap_or(&path).components() {
+        match component {
+            std::path::Component::ParentDir => {
+                resolved.pop();
+            }
+            std::path::Component::CurDir => (),
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+/// Compares device and inode numbers, so hard links and bind mounts of the
+/// source are also seen as the source
+fn same_file(a: &std::path::Path, b: &std::path::Path) -> Result<bool, PonoError> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = |path: &std::path::Path| {
+        std::fs::metadata(path).map_err(|err| {
+            PonoError::Unhandled(format!(
+                "Failed to read metadata of {}: {}",
+                path.display(),
+                err
+            ))
+        })
+    };
+    let (a, b) = (metadata(a)?, metadata(b)?);
+    Ok(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+fn file_type_name(metadata: &std::fs::Metadata) -> &'static str {
+    if metadata.is_dir() {
+        "directory"
+    } else if metadata.is_file() {
+        "file"
+    } else {
+        "unknown"
+    }
+}
+
+fn join(base: &str, relative: &std::path::Path) -> String {
+    std::path::Path::new(base)
+        .join(relative)
+        .to_string_lossy()
+        .to_string()
+}
+
 fn path(path: &str) -> String {
     if path.starts_with("~") {
         return shellexpand::tilde(path).into_owned();
@@ -499,7 +1962,7 @@ fn path(path: &str) -> String {
 
     if path.contains("$") {
         let expanded = shellexpand::env(path)
-            .expect(format!("Failed to expand path: {}", path).as_str())
+            .unwrap_or_else(|_| panic!("Failed to expand path: {}", path))
             .into_owned();
         return expanded;
     }
@@ -517,25 +1980,104 @@ fn path(path: &str) -> String {
     absolute_path.to_string_lossy().to_string()
 }
 
-fn ponos_to_manipulate(config: &Configuration, ponos: &Option<Vec<String>>) -> Vec<String> {
-    let mut list: Vec<String> = config
-        .ponos
-        .keys()
-        .filter(|p| {
-            if ponos.is_none() {
-                return true;
-            }
+/// Group names and tags used in the configuration, sorted
+fn group_names(config: &Configuration) -> Vec<String> {
+    let mut names: Vec<String> = config
+        .groups
+        .iter()
+        .flat_map(|groups| groups.keys().cloned())
+        .chain(
+            config
+                .ponos
+                .values()
+                .flat_map(|pono| pono.tags.iter().flatten().cloned()),
+        )
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
 
-            if let Some(ref pckgs) = ponos {
-                if pckgs.contains(&p.to_string()) {
-                    return true;
-                }
-            }
+/// Adds the `--tag` values to the pono selectors as `@tag`
+fn with_tags(ponos: &Option<Vec<String>>, tags: &[String]) -> Option<Vec<String>> {
+    if tags.is_empty() {
+        return ponos.clone();
+    }
+    let mut selectors = ponos.clone().unwrap_or_default();
+    selectors.extend(tags.iter().map(|tag| format!("@{}", tag)));
+    Some(selectors)
+}
 
-            false
-        })
-        .map(|s| s.to_string())
-        .collect();
+fn is_pattern(selector: &str) -> bool {
+    selector.contains(['*', '?', '['])
+}
+
+/// Whether the pono is selected by a name, a glob pattern or by `@name`,
+/// which selects the members of the group `name` and the ponos tagged `name`
+fn is_selected(config: &Configuration, pono: &str, selector: &str) -> bool {
+    let Some(group) = selector.strip_prefix('@') else {
+        if is_pattern(selector) {
+            return glob::Pattern::new(selector).is_ok_and(|pattern| pattern.matches(pono));
+        }
+        return selector == pono;
+    };
+    let in_group = config
+        .groups
+        .as_ref()
+        .and_then(|groups| groups.get(group))
+        .is_some_and(|members| members.iter().any(|m| m == pono));
+    let is_tagged = config.ponos[pono]
+        .tags
+        .iter()
+        .flatten()
+        .any(|tag| tag == group);
+    in_group || is_tagged
+}
+
+/// Lists the ponos selected by names, `@group` or glob patterns such as
+/// `git:*`, sorted. Selectors that match no pono are an error.
+fn ponos_to_manipulate(
+    config: &Configuration,
+    ponos: &Option<Vec<String>>,
+) -> Result<Vec<String>, PonoError> {
+    let mut list: Vec<String> = config.ponos.keys().cloned().collect();
     list.sort();
-    list
+
+    let Some(selectors) = ponos else {
+        return Ok(list);
+    };
+
+    for selector in selectors {
+        if list.iter().any(|p| is_selected(config, p, selector)) {
+            continue;
+        }
+        return Err(PonoError::NotFound(
+            if let Some(group) = selector.strip_prefix('@') {
+                format!("No ponos in group or tag {}", group)
+            } else if is_pattern(selector) {
+                format!("No ponos match the pattern {}", selector)
+            } else {
+                format!("Pono not found {} in ponos list", selector)
+            },
+        ));
+    }
+
+    list.retain(|p| {
+        selectors
+            .iter()
+            .any(|selector| is_selected(config, p, selector))
+    });
+    Ok(list)
+}
+
+fn handle_selection_error(res: Result<Vec<String>, PonoError>) -> Vec<String> {
+    match res {
+        Ok(list) => list,
+        Err(err) => {
+            println_color!(RED, "Reason: {}", err);
+            println!("Debugging:");
+            println!(" - Run `pono list` to see the available ponos");
+            std::process::exit(1);
+        }
+    }
 }
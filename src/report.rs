@@ -0,0 +1,95 @@
+use serde::Serialize;
+
+use crate::{active_variant, check_package, linked_source, path, PonoDefinition, PonoError};
+
+/// Output formats of `status` and `list`
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// Human readable colored text
+    #[default]
+    Text,
+    /// A JSON array with one object per pono
+    Json,
+    /// Tab-separated values with a header line
+    Tsv,
+}
+
+/// Machine readable status of a pono
+#[derive(Serialize)]
+pub struct PonoReport {
+    pub name: String,
+    /// Expanded source path
+    pub source: String,
+    /// Expanded target path
+    pub target: String,
+    /// One of: linked, missing, dangling, broken, not-symlink, mismatch
+    pub state: &'static str,
+    /// Variant in use for ponos with variants
+    pub variant: Option<String>,
+    pub error_kind: Option<&'static str>,
+    pub error: Option<String>,
+}
+
+impl PonoReport {
+    pub fn new(name: &str, pono: &PonoDefinition) -> PonoReport {
+        let target = path(&pono.target);
+        let variant = active_variant(pono);
+        let result = match variant {
+            Some(_) => Ok(()),
+            None => check_package(pono),
+        };
+
+        let state = match &result {
+            Ok(_) => "linked",
+            Err(PonoError::NotFound(_)) if std::fs::symlink_metadata(&target).is_err() => "missing",
+            Err(PonoError::NotSymlink(_)) => "not-symlink",
+            Err(PonoError::LinkMismatch(_)) => "mismatch",
+            Err(PonoError::Dangling(_)) => "dangling",
+            Err(_) => "broken",
+        };
+
+        PonoReport {
+            name: name.to_string(),
+            source: path(&linked_source(pono)),
+            target,
+            state,
+            variant,
+            error_kind: result.as_ref().err().map(|err| err.kind()),
+            error: result.err().map(|err| err.to_string()),
+        }
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.state == "linked"
+    }
+}
+
+/// Prints the reports as JSON or TSV
+pub fn print_reports(reports: &[PonoReport], format: Format) {
+    match format {
+        Format::Text => (),
+        Format::Json => {
+            let json = serde_json::to_string_pretty(reports).expect("Failed to serialize reports");
+            println!("{}", json);
+        }
+        Format::Tsv => {
+            println!("name\tsource\ttarget\tstate\tvariant\terror_kind\terror");
+            for report in reports {
+                let fields = [
+                    report.name.as_str(),
+                    &report.source,
+                    &report.target,
+                    report.state,
+                    report.variant.as_deref().unwrap_or_default(),
+                    report.error_kind.unwrap_or_default(),
+                    report.error.as_deref().unwrap_or_default(),
+                ];
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|field| field.replace(['\t', '\n'], " "))
+                    .collect();
+                println!("{}", fields.join("\t"));
+            }
+        }
+    }
+}
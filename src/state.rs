@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backup;
+use crate::resolve;
+use crate::transaction::{Operation, Step};
+
+/// A link pono created, still in place as far as pono knows
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkRecord {
+    /// Path of the config file the pono was defined in
+    pub config: String,
+    pub pono: String,
+    pub source: String,
+    pub target: String,
+    /// When the link was made, as `YYYYMMDDhhmmss` in UTC
+    pub created: String,
+}
+
+/// A backup pono made of a target, not restored yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupRecord {
+    pub config: String,
+    pub pono: String,
+    pub target: String,
+    pub backup: String,
+    pub created: String,
+}
+
+/// What pono made on this machine, kept between runs in
+/// `$XDG_STATE_HOME/pono/state.toml` (default: `~/.local/state/pono`).
+/// Paths are absolute so the state doesn't depend on the current directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    pub links: Vec<LinkRecord>,
+    #[serde(default)]
+    pub backups: Vec<BackupRecord>,
+}
+
+impl State {
+    /// Reads the state file, empty when pono never wrote it
+    pub fn load() -> Result<State, String> {
+        let state_path = state_path();
+        let content = match std::fs::read_to_string(&state_path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(State::default()),
+            Err(err) => return Err(format!("{}: {}", state_path.display(), err)),
+        };
+        toml::from_str(&content).map_err(|err| format!("{}: {}", state_path.display(), err))
+    }
+
+    /// Writes the state file, replacing it in one step so a concurrent run
+    /// never reads it half written
+    pub fn save(&self) -> Result<(), String> {
+        let state_path = state_path();
+        let content = toml::to_string(self).map_err(|err| err.to_string())?;
+        let tmp_path = state_path.with_extension(format!("{}.tmp", std::process::id()));
+        state_path
+            .parent()
+            .map(std::fs::create_dir_all)
+            .transpose()
+            .and_then(|_| std::fs::write(&tmp_path, content))
+            .and_then(|_| std::fs::rename(&tmp_path, &state_path))
+            .map_err(|err| format!("{}: {}", state_path.display(), err))
+    }
+
+    /// Updates the records with the steps applied for the ponos of `config`
+    pub fn record(&mut self, config: &str, steps: &[Step]) {
+        let config = absolute(config);
+        let created = backup::now();
+        for step in steps {
+            match &step.operation {
+                Operation::Link { source, target } | Operation::Relink { source, target } => {
+                    let target = absolute(target);
+                    self.links.retain(|link| link.target != target);
+                    // relative links point from the directory of the link
+                    let source = Path::new(&target)
+                        .parent()
+                        .map(|dir| resolve(&dir.join(source)))
+                        .unwrap_or_else(|| PathBuf::from(source));
+                    self.links.push(LinkRecord {
+                        config: config.clone(),
+                        pono: step.pono.clone(),
+                        source: source.to_string_lossy().to_string(),
+                        target,
+                        created: created.clone(),
+                    });
+                }
+                Operation::Unlink { target } => {
+                    let target = absolute(target);
+                    self.links.retain(|link| link.target != target);
+                }
+                Operation::Rename { from, to } => {
+                    let (from, to) = (absolute(from), absolute(to));
+                    // a link moved aside is no longer in place
+                    self.links.retain(|link| link.target != from);
+                    self.backups.retain(|backup| backup.backup != from);
+                    if backup::is_backup(&to) {
+                        self.backups.push(BackupRecord {
+                            config: config.clone(),
+                            pono: step.pono.clone(),
+                            target: from,
+                            backup: to,
+                            created: created.clone(),
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// The links made for the ponos of the config file
+    pub fn links_of<'a>(&'a self, config: &str) -> impl Iterator<Item = &'a LinkRecord> {
+        let config = absolute(config);
+        self.links.iter().filter(move |link| link.config == config)
+    }
+
+    /// Whether pono made the link at `target` for the ponos of the config
+    /// file, and the link still points to the source it was made for
+    pub fn made_link(&self, config: &str, target: &str) -> bool {
+        let target = absolute(target);
+        let Ok(points_to) = std::fs::read_link(&target) else {
+            return false;
+        };
+        let dir = Path::new(&target).parent().unwrap_or(Path::new("/"));
+        let points_to = resolve(&dir.join(points_to));
+        self.links_of(config)
+            .any(|link| link.target == target && resolve(Path::new(&link.source)) == points_to)
+    }
+}
+
+/// Where the state is kept, following the XDG base directories
+pub fn state_path() -> PathBuf {
+    let state_home = match std::env::var("XDG_STATE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(".local/state"),
+    };
+    state_home.join("pono").join("state.toml")
+}
+
+/// The path made absolute from the current directory, without following links
+pub fn absolute(path: &str) -> String {
+    std::path::absolute(Path::new(path))
+        .unwrap_or_else(|_| PathBuf::from(path))
+        .to_string_lossy()
+        .to_string()
+}
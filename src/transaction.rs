@@ -0,0 +1,238 @@
+use std::fmt::{Display, Formatter};
+use std::os::unix::fs::symlink;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hooks::HookRun;
+use crate::PonoError;
+
+/// A single filesystem change planned for a pono
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    /// Creates a symlink at `target` pointing to `source`
+    Link { source: String, target: String },
+    /// Removes the symlink at `target`
+    Unlink { target: String },
+    /// Points the existing symlink at `target` to `source` in a single step
+    Relink { source: String, target: String },
+    /// Moves `from` to `to` (used to back up existing targets)
+    Rename { from: String, to: String },
+    /// Creates the directory at `path`, its parent must exist
+    CreateDir { path: String },
+    /// Removes the empty directory at `path`
+    RemoveDir { path: String },
+    /// Runs a pono hook. Hooks can't be reverted nor kept in the history.
+    #[serde(skip)]
+    Hook(HookRun),
+}
+
+impl Operation {
+    /// Applies the operation and returns the operation that reverts it, if any
+    fn apply(&self) -> Result<Option<Operation>, PonoError> {
+        match self {
+            Operation::Link { source, target } => {
+                symlink(source, target).map_err(|err| PonoError::Unhandled(err.to_string()))?;
+                Ok(Some(Operation::Unlink {
+                    target: target.clone(),
+                }))
+            }
+            Operation::Unlink { target } => {
+                let points_to = std::fs::read_link(target)
+                    .map_err(|err| PonoError::Unhandled(err.to_string()))?;
+                std::fs::remove_file(target)
+                    .map_err(|err| PonoError::Unhandled(err.to_string()))?;
+                Ok(Some(Operation::Link {
+                    source: points_to.to_string_lossy().to_string(),
+                    target: target.clone(),
+                }))
+            }
+            Operation::Relink { source, target } => {
+                let points_to = std::fs::read_link(target)
+                    .map_err(|err| PonoError::Unhandled(err.to_string()))?;
+                // Renaming a new link over the old one replaces it atomically
+                let tmp_link = format!("{}.pono-tmp", target);
+                symlink(source, &tmp_link).map_err(|err| PonoError::Unhandled(err.to_string()))?;
+                if let Err(err) = std::fs::rename(&tmp_link, target) {
+                    std::fs::remove_file(&tmp_link).ok();
+                    return Err(PonoError::Unhandled(err.to_string()));
+                }
+                Ok(Some(Operation::Relink {
+                    source: points_to.to_string_lossy().to_string(),
+                    target: target.clone(),
+                }))
+            }
+            Operation::Rename { from, to } => {
+                std::fs::rename(from, to).map_err(|err| PonoError::Unhandled(err.to_string()))?;
+                Ok(Some(Operation::Rename {
+                    from: to.clone(),
+                    to: from.clone(),
+                }))
+            }
+            Operation::CreateDir { path } => {
+                std::fs::create_dir(path).map_err(|err| PonoError::Unhandled(err.to_string()))?;
+                Ok(Some(Operation::RemoveDir { path: path.clone() }))
+            }
+            Operation::RemoveDir { path } => {
+                std::fs::remove_dir(path).map_err(|err| PonoError::Unhandled(err.to_string()))?;
+                Ok(Some(Operation::CreateDir { path: path.clone() }))
+            }
+            Operation::Hook(hook) => {
+                hook.run()?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Whether a failure stops only the pono it belongs to instead of the
+    /// whole transaction: `pre-` hooks run before the pono changes anything
+    fn is_gate(&self) -> bool {
+        matches!(self, Operation::Hook(hook) if hook.event.starts_with("pre-"))
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::Link { source, target } => write!(f, "create link {} -> {}", target, source),
+            Operation::Unlink { target } => write!(f, "remove link {}", target),
+            Operation::Relink { source, target } => {
+                write!(f, "relink {} -> {}", target, source)
+            }
+            Operation::Rename { from, to } => write!(f, "rename {} -> {}", from, to),
+            Operation::CreateDir { path } => write!(f, "create directory {}", path),
+            Operation::RemoveDir { path } => write!(f, "remove directory {}", path),
+            Operation::Hook(hook) => write!(f, "{}", hook),
+        }
+    }
+}
+
+/// An operation and the pono it belongs to
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub pono: String,
+    pub operation: Operation,
+    /// The operation reverting it, once applied
+    pub revert: Option<Operation>,
+}
+
+/// Describes why a transaction failed and what was reverted
+pub struct Failure {
+    pub step: Step,
+    pub error: PonoError,
+    /// Steps that were applied and then reverted, in revert order
+    pub rolled_back: Vec<Step>,
+    /// Steps that could not be reverted and need manual intervention
+    pub rollback_errors: Vec<(Step, PonoError)>,
+    /// The error hooks of the failed pono, run in order after the rollback,
+    /// and their error. The hooks after a failing one don't run.
+    pub error_hooks: Vec<(Step, Option<PonoError>)>,
+}
+
+/// A plan of operations applied as one unit: either every step succeeds or
+/// the steps already applied are reverted.
+#[derive(Default)]
+pub struct Transaction {
+    steps: Vec<Step>,
+    /// Hooks run when a step of their pono fails, in the order they were added
+    error_hooks: Vec<Step>,
+    /// History entries the transaction reverts
+    undoes: Vec<u64>,
+}
+
+impl Transaction {
+    pub fn add(&mut self, pono: &str, operation: Operation) {
+        self.steps.push(Step {
+            pono: pono.to_string(),
+            operation,
+            revert: None,
+        });
+    }
+
+    /// Runs `operation` after the rollback if a step of the pono fails, after
+    /// the error hooks already added for it
+    pub fn add_error_hook(&mut self, pono: &str, operation: Operation) {
+        self.error_hooks.push(Step {
+            pono: pono.to_string(),
+            operation,
+            revert: None,
+        });
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn error_hooks(&self) -> &[Step] {
+        &self.error_hooks
+    }
+
+    /// Marks the transaction as reverting the given history entries
+    pub fn set_undoes(&mut self, ids: Vec<u64>) {
+        self.undoes = ids;
+    }
+
+    pub fn undoes(&self) -> &[u64] {
+        &self.undoes
+    }
+
+    /// Applies every step in order, calling `on_applied` after each one.
+    /// On the first failure the applied steps are reverted in reverse order,
+    /// except for failing `pre-` hooks that only stop the rest of their pono.
+    /// Returns the hooks that stopped a pono.
+    pub fn commit<F: FnMut(&Step)>(
+        self,
+        mut on_applied: F,
+    ) -> Result<Vec<(Step, PonoError)>, Box<Failure>> {
+        let mut applied: Vec<Step> = vec![];
+        let mut stopped: Vec<(Step, PonoError)> = vec![];
+        for step in self.steps {
+            if stopped.iter().any(|(gate, _)| gate.pono == step.pono) {
+                continue;
+            }
+            match step.operation.apply() {
+                Ok(revert) => {
+                    let step = Step { revert, ..step };
+                    on_applied(&step);
+                    applied.push(step);
+                }
+                Err(error) if step.operation.is_gate() => stopped.push((step, error)),
+                Err(error) => {
+                    let (rolled_back, rollback_errors) = rollback(applied);
+                    let mut error_hooks = vec![];
+                    for hook in self.error_hooks.into_iter().filter(|h| h.pono == step.pono) {
+                        let error = hook.operation.apply().err();
+                        let failed = error.is_some();
+                        error_hooks.push((hook, error));
+                        if failed {
+                            break;
+                        }
+                    }
+                    return Err(Box::new(Failure {
+                        step,
+                        error,
+                        rolled_back,
+                        rollback_errors,
+                        error_hooks,
+                    }));
+                }
+            }
+        }
+        Ok(stopped)
+    }
+}
+
+fn rollback(applied: Vec<Step>) -> (Vec<Step>, Vec<(Step, PonoError)>) {
+    let mut rolled_back = vec![];
+    let mut errors = vec![];
+    for step in applied.into_iter().rev() {
+        let Some(revert) = &step.revert else {
+            continue;
+        };
+        match revert.apply() {
+            Ok(_) => rolled_back.push(step),
+            Err(err) => errors.push((step, err)),
+        }
+    }
+    (rolled_back, errors)
+}
@@ -0,0 +1,129 @@
+use glob::{MatchOptions, Pattern, PatternError};
+use std::path::{Path, PathBuf};
+
+/// A file or directory found under the source of a `tree` pono
+pub struct Entry {
+    /// Path relative to the pono source
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// The include pattern that matched the file, if any
+    pub included_by: Option<String>,
+}
+
+/// A file or directory left out by the pono patterns
+pub struct Skipped {
+    /// Path relative to the pono source
+    pub path: PathBuf,
+    /// The exclude pattern that matched, none when no include pattern matched
+    pub excluded_by: Option<String>,
+}
+
+/// Entries found walking a pono source
+#[derive(Default)]
+pub struct Walk {
+    pub entries: Vec<Entry>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// Include and exclude glob patterns of a pono. A pattern without `/`
+/// matches the file name at any depth (e.g. `*.swp`), otherwise it matches
+/// the path relative to the pono source (e.g. `lua/*.lua`).
+#[derive(Default)]
+pub struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Filter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Filter, PatternError> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| Pattern::new(pattern))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Filter {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    fn excluded_by(&self, path: &Path) -> Option<&Pattern> {
+        self.exclude.iter().find(|pattern| matches(pattern, path))
+    }
+
+    /// Checks a file against the include patterns. Everything is included
+    /// when there are no include patterns.
+    fn included_by(&self, path: &Path) -> Result<Option<&Pattern>, ()> {
+        if self.include.is_empty() {
+            return Ok(None);
+        }
+        match self.include.iter().find(|pattern| matches(pattern, path)) {
+            Some(pattern) => Ok(Some(pattern)),
+            None => Err(()),
+        }
+    }
+}
+
+fn matches(pattern: &Pattern, path: &Path) -> bool {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    if pattern.as_str().contains('/') {
+        return pattern.matches_path_with(path, options);
+    }
+    path.file_name()
+        .is_some_and(|name| pattern.matches_with(&name.to_string_lossy(), options))
+}
+
+/// Lists every entry under `source` recursively, sorted by name, with each
+/// directory listed before its content. Symlinks are not followed.
+/// Excluded directories are skipped with all their content.
+pub fn walk(source: &Path, filter: &Filter) -> std::io::Result<Walk> {
+    let mut walk = Walk::default();
+    walk_dir(source, Path::new(""), filter, &mut walk)?;
+    Ok(walk)
+}
+
+fn walk_dir(root: &Path, relative: &Path, filter: &Filter, walk: &mut Walk) -> std::io::Result<()> {
+    let mut children = std::fs::read_dir(root.join(relative))?.collect::<Result<Vec<_>, _>>()?;
+    children.sort_by_key(|child| child.file_name());
+
+    for child in children {
+        let path = relative.join(child.file_name());
+        let is_dir = child.file_type()?.is_dir();
+
+        if let Some(pattern) = filter.excluded_by(&path) {
+            walk.skipped.push(Skipped {
+                path,
+                excluded_by: Some(pattern.to_string()),
+            });
+            continue;
+        }
+
+        if is_dir {
+            walk.entries.push(Entry {
+                path: path.clone(),
+                is_dir,
+                included_by: None,
+            });
+            walk_dir(root, &path, filter, walk)?;
+            continue;
+        }
+
+        match filter.included_by(&path) {
+            Ok(pattern) => walk.entries.push(Entry {
+                path,
+                is_dir,
+                included_by: pattern.map(|p| p.to_string()),
+            }),
+            Err(_) => walk.skipped.push(Skipped {
+                path,
+                excluded_by: None,
+            }),
+        }
+    }
+
+    Ok(())
+}
@@ -1,15 +1,12 @@
-use assert_cmd::Command;
 use predicates::prelude::predicate;
 
 #[path = "./common/lib.rs"]
 mod common;
 
-const BINARY_NAME: &str = "pono";
-
 #[test]
 fn it_allows_using_environment_variables() -> Result<(), Box<dyn std::error::Error>> {
     common::cleanup();
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
 
     cmd.arg("-c")
         .arg("examples/configs/using-environment-variables.toml")
@@ -22,7 +19,7 @@ fn it_allows_using_environment_variables() -> Result<(), Box<dyn std::error::Err
         .success()
         .stdout(predicate::str::contains(expected_output));
 
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
     cmd.arg("-c")
         .arg("examples/configs/using-environment-variables.toml")
         .arg("disable")
@@ -38,7 +35,7 @@ fn it_allows_using_environment_variables() -> Result<(), Box<dyn std::error::Err
 #[test]
 fn it_executes_pre_enable_and_disable_hook_when_configured(
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
 
     cmd.arg("-c")
         .arg("examples/basic.toml")
@@ -49,7 +46,7 @@ fn it_executes_pre_enable_and_disable_hook_when_configured(
         "Running pre_enable hook for with-hooks",
     ));
 
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
 
     cmd.arg("-c")
         .arg("examples/basic.toml")
@@ -74,7 +71,7 @@ fn it_allows_using_tilda_to_express_homedir() -> Result<(), Box<dyn std::error::
         std::path::Path::new(std::env::var("HOME")?.as_str()).join("__pono_test__"),
     )
     .ok();
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
 
     cmd.arg("-c")
         .arg("examples/configs/using-environment-variables.toml")
@@ -85,7 +82,7 @@ fn it_allows_using_tilda_to_express_homedir() -> Result<(), Box<dyn std::error::
         "home  $PWD/examples/from/other -> ~/__pono_test__ (linking)",
     ));
 
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
     cmd.arg("-c")
         .arg("examples/configs/using-environment-variables.toml")
         .arg("disable")
@@ -97,3 +94,1534 @@ fn it_allows_using_tilda_to_express_homedir() -> Result<(), Box<dyn std::error::
 
     Ok(())
 }
+
+#[test]
+fn it_links_each_file_of_a_tree_pono() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("tree-mode");
+    common::write(&ws, "dotfiles/nvim/init.lua", "-- init");
+    common::write(&ws, "dotfiles/nvim/lua/plugins.lua", "-- plugins");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos.nvim]
+source = "dotfiles/nvim"
+target = "home/.config/nvim"
+mode = "tree"
+"#,
+    );
+
+    // missing parents of the target are created too
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("--dry-run");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "nvim: create directory {}/home\n",
+            ws.display()
+        )))
+        .stdout(predicate::str::contains(format!(
+            "nvim: create directory {}/home/.config/nvim\n",
+            ws.display()
+        )));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "home/.config/nvim/init.lua (new link)",
+        ))
+        .stdout(predicate::str::contains(
+            "home/.config/nvim/lua/plugins.lua (new link)",
+        ));
+
+    let target = ws.join("home/.config/nvim");
+    assert!(std::fs::symlink_metadata(ws.join("home/.config"))?.is_dir());
+    assert!(!std::fs::symlink_metadata(&target)?.is_symlink());
+    assert!(std::fs::symlink_metadata(target.join("lua"))?.is_dir());
+    assert!(std::fs::symlink_metadata(target.join("init.lua"))?.is_symlink());
+    assert!(std::fs::symlink_metadata(target.join("lua/plugins.lua"))?.is_symlink());
+
+    // Files created by apps stay out of the dotfiles
+    std::fs::write(target.join("state.json"), "{}")?;
+    assert!(!ws.join("dotfiles/nvim/state.json").exists());
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("status");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("nvim home/.config/nvim (linked)"));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("disable");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Unlinked pono: nvim"));
+
+    assert!(std::fs::symlink_metadata(target.join("init.lua")).is_err());
+    assert!(std::fs::symlink_metadata(target.join("lua/plugins.lua")).is_err());
+    assert!(target.join("state.json").exists());
+
+    Ok(())
+}
+
+#[test]
+fn it_skips_files_matching_exclude_patterns() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("patterns");
+    common::write(&ws, "dotfiles/vim/vimrc", "set number");
+    common::write(&ws, "dotfiles/vim/.vimrc.swp", "");
+    common::write(&ws, "dotfiles/vim/.DS_Store", "");
+    common::write(&ws, "dotfiles/vim/README.md", "# vim");
+    common::write(&ws, "dotfiles/vim/colors/dark.vim", "");
+    common::write(&ws, "dotfiles/vim/node_modules/pkg/index.js", "");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos.vim]
+source = "dotfiles/vim"
+target = "vim"
+exclude = ["*.swp", ".DS_Store", "README.md", "node_modules"]
+
+[ponos.colors]
+source = "dotfiles/vim"
+target = "colors"
+include = ["colors/*.vim"]
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("exclude *.swp: .vimrc.swp"))
+        .stdout(predicate::str::contains(
+            "exclude node_modules: node_modules",
+        ))
+        .stdout(predicate::str::contains(
+            "include colors/*.vim: colors/dark.vim",
+        ))
+        .stdout(predicate::str::contains("not included: .DS_Store"));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("vim");
+    cmd.assert().success();
+
+    assert!(std::fs::symlink_metadata(ws.join("vim/vimrc"))?.is_symlink());
+    assert!(std::fs::symlink_metadata(ws.join("vim/colors/dark.vim"))?.is_symlink());
+    assert!(!ws.join("vim/.vimrc.swp").exists());
+    assert!(!ws.join("vim/.DS_Store").exists());
+    assert!(!ws.join("vim/README.md").exists());
+    assert!(!ws.join("vim/node_modules").exists());
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("status").arg("vim");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("vim vim (linked)"))
+        .stdout(predicate::str::contains("exclude README.md: README.md"));
+
+    Ok(())
+}
+
+#[test]
+fn it_switches_between_pono_variants() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("variants");
+    common::write(&ws, "alacritty-transparent.yml", "opacity: 0.8");
+    common::write(&ws, "alacritty-opaque.yml", "opacity: 1");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos.alacritty]
+target = "alacritty.yml"
+
+[ponos.alacritty.variants]
+transparent = { source = "alacritty-transparent.yml", default = true }
+opaque = { source = "alacritty-opaque.yml" }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable");
+    cmd.assert().success();
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("status");
+    cmd.assert().success().stdout(predicate::str::contains(
+        "alacritty alacritty.yml (linked, variant transparent)",
+    ));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("toggle")
+        .arg("alacritty")
+        .arg("opaque");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("(variant opaque)"));
+    assert_eq!(
+        std::fs::read_to_string(ws.join("alacritty.yml"))?,
+        "opacity: 1"
+    );
+    assert!(!ws.join("alacritty.yml.bak").exists());
+
+    // Without a variant it cycles to the next one
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("toggle").arg("alacritty");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("(variant transparent)"));
+    assert_eq!(
+        std::fs::read_to_string(ws.join("alacritty.yml"))?,
+        "opacity: 0.8"
+    );
+
+    // variants are checked by pono, whatever the name of the config file
+    std::fs::copy(ws.join("pono.toml"), ws.join("ponorc"))?;
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("-c")
+        .arg("ponorc")
+        .arg("toggle")
+        .arg("alacritty")
+        .arg("dark");
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "Variant dark not found for pono alacritty",
+    ));
+
+    // links to any variant are owned by pono
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("-c")
+        .arg("ponorc")
+        .arg("toggle")
+        .arg("alacritty")
+        .arg("opaque");
+    cmd.assert().success();
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable");
+    cmd.assert().success().stdout(predicate::str::contains(
+        "alacritty: alacritty.yml (already linked)",
+    ));
+    assert_eq!(
+        std::fs::read_to_string(ws.join("alacritty.yml"))?,
+        "opacity: 1"
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("sync");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1 ponos already in sync"));
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("status");
+    cmd.assert().success().stdout(predicate::str::contains(
+        "alacritty alacritty.yml (linked, variant opaque)",
+    ));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("disable");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Unlinked pono: alacritty"));
+    assert!(std::fs::symlink_metadata(ws.join("alacritty.yml")).is_err());
+    assert_eq!(
+        std::fs::read_to_string(ws.join("alacritty-opaque.yml"))?,
+        "opacity: 1"
+    );
+
+    // links to anything else are backed up, not switched
+    common::write(&ws, "other.yml", "other");
+    std::os::unix::fs::symlink(ws.join("other.yml"), ws.join("alacritty.yml"))?;
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("toggle")
+        .arg("alacritty")
+        .arg("opaque");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("(backup)"))
+        .stdout(predicate::str::contains("(variant opaque)"));
+    assert_eq!(
+        std::fs::read_to_string(ws.join("alacritty.yml"))?,
+        "opacity: 1"
+    );
+    let backups: Vec<_> = std::fs::read_dir(&ws)?
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.to_string_lossy().contains("alacritty.yml.pono-"))
+        .collect();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(std::fs::read_link(&backups[0])?, ws.join("other.yml"));
+
+    Ok(())
+}
+
+#[test]
+fn it_keeps_a_backup_stack_when_toggling() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("toggle-backups");
+    common::write(&ws, ".env", "ORIGINAL");
+    common::write(&ws, ".env.bak", "NOT MADE BY PONO");
+    common::write(&ws, "envs/develop", "DEVELOP");
+    common::write(&ws, "envs/stage", "STAGE");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+develop = { source = "envs/develop", target = ".env" }
+stage = { source = "envs/stage", target = ".env" }
+"#,
+    );
+    let backups = |ws: &std::path::Path| {
+        std::fs::read_dir(ws)
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with(".env.pono-")
+            })
+            .count()
+    };
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("toggle").arg("develop");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("(backup)"))
+        .stdout(predicate::str::contains("(new link)"));
+    assert_eq!(std::fs::read_to_string(ws.join(".env"))?, "DEVELOP");
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("toggle").arg("stage");
+    cmd.assert().success();
+    assert_eq!(std::fs::read_to_string(ws.join(".env"))?, "STAGE");
+    assert_eq!(backups(&ws), 2);
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("restore").arg("stage");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("(restored from"));
+    assert_eq!(std::fs::read_to_string(ws.join(".env"))?, "DEVELOP");
+
+    // Toggling the active pono switches back to the original file
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("toggle").arg("develop");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("(unlinked)"))
+        .stdout(predicate::str::contains("(restored)"));
+    assert!(!std::fs::symlink_metadata(ws.join(".env"))?.is_symlink());
+    assert_eq!(std::fs::read_to_string(ws.join(".env"))?, "ORIGINAL");
+    assert_eq!(backups(&ws), 0);
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("restore").arg("develop");
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "No backups found for pono develop",
+    ));
+
+    assert_eq!(
+        std::fs::read_to_string(ws.join(".env.bak"))?,
+        "NOT MADE BY PONO"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_selects_ponos_by_group_and_tag() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("groups");
+    common::write(&ws, "dotfiles/zshrc", "zsh");
+    common::write(&ws, "dotfiles/bashrc", "bash");
+    common::write(&ws, "dotfiles/gitconfig", "git");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[groups]
+shell = ["zsh", "bash"]
+
+[ponos]
+zsh = { source = "dotfiles/zshrc", target = ".zshrc" }
+bash = { source = "dotfiles/bashrc", target = ".bashrc" }
+gitconfig = { source = "dotfiles/gitconfig", target = ".gitconfig", tags = ["git"] }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("@shell");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("zsh: .zshrc (new link)"))
+        .stdout(predicate::str::contains("bash: .bashrc (new link)"))
+        .stdout(predicate::str::contains("gitconfig").count(0));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("--tag").arg("git");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("gitconfig: .gitconfig (new link)"))
+        .stdout(predicate::str::contains("zsh").count(0));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("status").arg("@git");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("gitconfig .gitconfig (linked)"))
+        .stdout(predicate::str::contains("zsh").count(0));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("completions").arg("bash");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("@shell"))
+        .stdout(predicate::str::contains("@git"));
+
+    Ok(())
+}
+
+#[test]
+fn it_selects_ponos_by_glob_pattern() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("glob-selectors");
+    common::write(&ws, "hooks/checks", "#!/bin/sh");
+    common::write(&ws, "nvim/init.lua", "");
+    std::fs::create_dir_all(ws.join("git-hooks"))?;
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+"git:pre-commit" = { source = "hooks/checks", target = "git-hooks/pre-commit" }
+"git:pre-push" = { source = "hooks/checks", target = "git-hooks/pre-push" }
+nvim = { source = "nvim", target = "nvim-config" }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("git:*");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "git:pre-commit: git-hooks/pre-commit (new link)",
+        ))
+        .stdout(predicate::str::contains(
+            "git:pre-push: git-hooks/pre-push (new link)",
+        ))
+        .stdout(predicate::str::contains("nvim").count(0));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("status").arg("nv*");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("nvim nvim-config (broken)"))
+        .stdout(predicate::str::contains("git:").count(0));
+
+    Ok(())
+}
+
+#[test]
+fn it_adopts_the_current_target_into_the_source() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("adopt");
+    common::write(&ws, "home/.zshrc", "CURRENT ZSH");
+    common::write(&ws, "home/.gitconfig", "CURRENT GIT");
+    common::write(&ws, "dotfiles/gitconfig", "OLD GIT");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+zsh = { source = "dotfiles/shell/zshrc", target = "home/.zshrc" }
+git = { source = "dotfiles/gitconfig", target = "home/.gitconfig" }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("zsh");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(" - Run `pono adopt zsh`"));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("adopt").arg("zsh");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("home/.zshrc -> "))
+        .stdout(predicate::str::contains("dotfiles/shell/zshrc (adopted)"));
+    assert_eq!(
+        std::fs::read_to_string(ws.join("dotfiles/shell/zshrc"))?,
+        "CURRENT ZSH"
+    );
+    assert!(std::fs::symlink_metadata(ws.join("home/.zshrc"))?.is_symlink());
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("adopt").arg("zsh");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Pono zsh is already linked"));
+
+    // an existing source is only replaced once confirmed
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("adopt")
+        .arg("git")
+        .write_stdin("n\n");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("-OLD GIT"))
+        .stdout(predicate::str::contains("+CURRENT GIT"))
+        .stdout(predicate::str::contains("Pono git was not adopted"));
+    assert_eq!(
+        std::fs::read_to_string(ws.join("dotfiles/gitconfig"))?,
+        "OLD GIT"
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("adopt")
+        .arg("git")
+        .write_stdin("y\n");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("dotfiles/gitconfig (adopted)"));
+    assert_eq!(
+        std::fs::read_to_string(ws.join("home/.gitconfig"))?,
+        "CURRENT GIT"
+    );
+    let backups: Vec<String> = std::fs::read_dir(ws.join("dotfiles"))?
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with("gitconfig.pono-"))
+        .collect();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(
+        std::fs::read_to_string(ws.join("dotfiles").join(&backups[0]))?,
+        "OLD GIT"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_resolves_existing_targets_with_on_conflict() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("on-conflict");
+    for name in ["zshrc", "vimrc", "tmux.conf", "inputrc"] {
+        common::write(&ws, &format!("dotfiles/{}", name), "FROM DOTFILES");
+        common::write(&ws, &format!("home/.{}", name), "CURRENT");
+    }
+    common::write(&ws, "home/.config/nvim/init.lua", "CURRENT");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+zsh = { source = "dotfiles/zshrc", target = "home/.zshrc", on_conflict = "backup" }
+nvim = { source = "dotfiles/nvim", target = "home/.config/nvim", on_conflict = "adopt" }
+vim = { source = "dotfiles/vimrc", target = "home/.vimrc", on_conflict = "overwrite" }
+tmux = { source = "dotfiles/tmux.conf", target = "home/.tmux.conf", on_conflict = "skip" }
+inputrc = { source = "dotfiles/inputrc", target = "home/.inputrc" }
+"#,
+    );
+
+    // `fail` is the default
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("Invalid ponos: inputrc"));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("enable")
+        .arg("zsh")
+        .arg("nvim")
+        .arg("vim")
+        .arg("tmux");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("zsh: "))
+        .stdout(predicate::str::contains(".zshrc.pono-"))
+        .stdout(predicate::str::contains("(backup)"))
+        .stdout(predicate::str::contains("dotfiles/nvim (adopted)"))
+        .stdout(predicate::str::contains("home/.vimrc (overwritten)"))
+        .stdout(predicate::str::contains(
+            "home/.tmux.conf (skipped, target exists)",
+        ));
+
+    let home: Vec<String> = std::fs::read_dir(ws.join("home"))?
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.contains(".pono-"))
+        .collect();
+    assert_eq!(home.len(), 1, "only the backup is left: {:?}", home);
+    assert!(home[0].starts_with(".zshrc.pono-"));
+    assert_eq!(
+        std::fs::read_to_string(ws.join("home").join(&home[0]))?,
+        "CURRENT"
+    );
+    assert_eq!(
+        std::fs::read_to_string(ws.join("home/.zshrc"))?,
+        "FROM DOTFILES"
+    );
+    assert_eq!(
+        std::fs::read_to_string(ws.join("home/.vimrc"))?,
+        "FROM DOTFILES"
+    );
+    assert_eq!(
+        std::fs::read_to_string(ws.join("dotfiles/nvim/init.lua"))?,
+        "CURRENT"
+    );
+    assert!(std::fs::symlink_metadata(ws.join("home/.config/nvim"))?.is_symlink());
+    assert!(!std::fs::symlink_metadata(ws.join("home/.tmux.conf"))?.is_symlink());
+
+    // the command line overrides the pono policy
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("enable")
+        .arg("inputrc")
+        .arg("--on-conflict=backup");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("(backup)"));
+    assert_eq!(
+        std::fs::read_to_string(ws.join("home/.inputrc"))?,
+        "FROM DOTFILES"
+    );
+
+    // adopting over an existing source asks like `pono adopt`
+    common::write(&ws, "dotfiles/gitconfig", "OLD GIT");
+    common::write(&ws, "home/.gitconfig", "CURRENT GIT");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+git = { source = "dotfiles/gitconfig", target = "home/.gitconfig", on_conflict = "adopt" }
+"#,
+    );
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").write_stdin("n\n");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("-OLD GIT"))
+        .stdout(predicate::str::contains("+CURRENT GIT"))
+        .stdout(predicate::str::contains("Pono git was not adopted"));
+    assert_eq!(
+        std::fs::read_to_string(ws.join("dotfiles/gitconfig"))?,
+        "OLD GIT"
+    );
+    assert!(!std::fs::symlink_metadata(ws.join("home/.gitconfig"))?.is_symlink());
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("--yes");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("dotfiles/gitconfig (adopted)"));
+    assert_eq!(
+        std::fs::read_to_string(ws.join("dotfiles/gitconfig"))?,
+        "CURRENT GIT"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_only_disables_links_owned_by_pono() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("disable-ownership");
+    common::write(&ws, "dotfiles/zshrc", "zsh");
+    common::write(&ws, "dotfiles/vimrc", "vim");
+    common::write(&ws, "elsewhere/vimrc", "not ours");
+    common::write(&ws, "home/.gitconfig", "real file");
+    std::os::unix::fs::symlink(ws.join("dotfiles/zshrc"), ws.join("home/.zshrc"))?;
+    std::os::unix::fs::symlink(ws.join("elsewhere/vimrc"), ws.join("home/.vimrc"))?;
+    // the source was removed after linking it
+    std::os::unix::fs::symlink(ws.join("dotfiles/tmux.conf"), ws.join("home/.tmux.conf"))?;
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+zsh = { source = "dotfiles/zshrc", target = "home/.zshrc" }
+vim = { source = "dotfiles/vimrc", target = "home/.vimrc" }
+git = { source = "dotfiles/gitconfig", target = "home/.gitconfig" }
+tmux = { source = "dotfiles/tmux.conf", target = "home/.tmux.conf" }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("disable");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("Invalid pono: git"))
+        .stdout(predicate::str::contains(
+            "Reason: (not-symlink) Target path 'home/.gitconfig' already exists and is a file.",
+        ))
+        .stdout(predicate::str::contains(
+            " - Run `pono disable git --force` to back up the target instead",
+        ));
+    assert!(std::fs::symlink_metadata(ws.join("home/.zshrc"))?.is_symlink());
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("disable").arg("vim");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("Reason: (link-mismatch)"));
+    assert!(std::fs::symlink_metadata(ws.join("home/.vimrc"))?.is_symlink());
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("disable").arg("zsh").arg("tmux");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Unlinked pono: zsh"))
+        .stdout(predicate::str::contains("Unlinked pono: tmux"));
+    assert!(std::fs::symlink_metadata(ws.join("home/.zshrc")).is_err());
+    assert!(std::fs::symlink_metadata(ws.join("home/.tmux.conf")).is_err());
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("disable")
+        .arg("vim")
+        .arg("git")
+        .arg("--force");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("home/.gitconfig -> "))
+        .stdout(predicate::str::contains("(backup)"));
+    let backups: Vec<String> = std::fs::read_dir(ws.join("home"))?
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.contains(".pono-"))
+        .collect();
+    assert_eq!(backups.len(), 2);
+    assert_eq!(
+        std::fs::read_to_string(ws.join("elsewhere/vimrc"))?,
+        "not ours"
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("disable").arg("zsh");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("zsh: home/.zshrc (not linked)"));
+
+    Ok(())
+}
+
+#[test]
+fn it_runs_hooks_around_each_change() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("hook-lifecycle");
+    common::write(&ws, "zshrc", "zsh");
+    common::write(&ws, "envs/develop", "DEVELOP");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos.zsh]
+source = "zshrc"
+target = ".zshrc"
+[ponos.zsh.hooks]
+pre_enable = "test ! -e .zshrc && echo pre_enable >> hooks.log"
+post_enable = "test -L .zshrc && echo post_enable >> hooks.log"
+pre_disable = "test -L .zshrc && echo pre_disable >> hooks.log"
+post_disable = "test ! -e .zshrc && echo post_disable >> hooks.log"
+on_error = "echo on_error zsh >> hooks.log"
+
+[ponos.env]
+source = "envs/develop"
+target = ".env"
+[ponos.env.hooks]
+pre_toggle = "echo pre_toggle >> hooks.log"
+post_toggle = "test -L .env && echo post_toggle >> hooks.log"
+
+[ponos.zsh-local]
+source = "zshrc"
+target = "missing/.zshrc"
+[ponos.zsh-local.hooks]
+post_enable = "echo post_enable broken >> hooks.log"
+on_error = ["echo on_error broken >> hooks.log", "echo on_error second >> hooks.log"]
+"#,
+    );
+    let log = || std::fs::read_to_string(ws.join("hooks.log")).unwrap_or_default();
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("zsh");
+    cmd.assert().success();
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("disable").arg("zsh");
+    cmd.assert().success();
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("toggle").arg("env");
+    cmd.assert().success();
+    assert_eq!(
+        log(),
+        "pre_enable\npost_enable\npre_disable\npost_disable\npre_toggle\npost_toggle\n"
+    );
+
+    // only the hooks of the pono that failed run, in order, after the rollback
+    std::fs::remove_file(ws.join("hooks.log"))?;
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("enable")
+        .arg("zsh-local")
+        .arg("--dry-run");
+    cmd.assert().success().stdout(predicate::str::contains(
+        "zsh-local: run on-error hook: echo on_error broken >> hooks.log (on error)\n  \
+         zsh-local: run on-error hook: echo on_error second >> hooks.log (on error)\n",
+    ));
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("enable")
+        .arg("zsh")
+        .arg("zsh-local");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("zsh: create link"))
+        .stdout(predicate::str::contains("(rolled back)"))
+        .stdout(predicate::str::contains(
+            "zsh-local: run on-error hook: echo on_error broken >> hooks.log (ran on error)",
+        ))
+        .stdout(predicate::str::contains(
+            "zsh-local: run on-error hook: echo on_error second >> hooks.log (ran on error)",
+        ));
+    assert_eq!(
+        log(),
+        "pre_enable\npost_enable\non_error broken\non_error second\n"
+    );
+    assert!(std::fs::symlink_metadata(ws.join(".zshrc")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn it_stops_a_pono_when_its_pre_hook_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("hook-exit-codes");
+    common::write(&ws, "source", "source");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos.gated]
+source = "source"
+target = "gated"
+hooks = { pre_enable = "echo 'not on this machine' >&2; exit 3" }
+
+[ponos.allowed]
+source = "source"
+target = "allowed"
+hooks = { pre_enable = { run = "exit 1", allow_failure = true } }
+
+[ponos.linked]
+source = "source"
+target = "linked"
+
+[ponos.post]
+source = "source"
+target = "post"
+hooks = { post_enable = "exit 2" }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("enable")
+        .arg("gated")
+        .arg("allowed")
+        .arg("linked");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("allowed: allowed (new link)"))
+        .stdout(predicate::str::contains("linked: linked (new link)"))
+        .stdout(predicate::str::contains(
+            "gated: run pre-enable hook: echo 'not on this machine' >&2; exit 3 (stopped)",
+        ))
+        .stdout(predicate::str::contains(
+            "Reason: (hook-failed) pre-enable hook exited with code 3: not on this machine",
+        ))
+        .stderr(predicate::str::contains("not on this machine"));
+    assert!(std::fs::symlink_metadata(ws.join("gated")).is_err());
+    assert!(std::fs::symlink_metadata(ws.join("allowed"))?.is_symlink());
+
+    // other hooks roll back the whole change
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("post");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "Pono link failed reason: (hook-failed) post-enable hook exited with code 2",
+        ))
+        .stdout(predicate::str::contains("(rolled back)"));
+    assert!(std::fs::symlink_metadata(ws.join("post")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn it_passes_the_pono_context_to_hooks() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("hook-env");
+    common::write(&ws, "dotfiles/zshrc", "zsh");
+    common::write(
+        &ws,
+        "scripts/log.sh",
+        "echo \"$PONO_ACTION $PONO_NAME $PONO_SOURCE $PONO_TARGET $PONO_CONFIG $(pwd) $SHELL_NAME\" >> \"$LOG\"",
+    );
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos.zsh]
+source = "dotfiles/zshrc"
+target = ".zshrc"
+
+[ponos.zsh.hooks]
+post_enable = "LOG=hooks.log sh scripts/log.sh"
+pre_disable = { run = "sh ../scripts/log.sh", cwd = "dotfiles", env = { LOG = "../hooks.log", SHELL_NAME = "zsh" } }
+
+[ponos.env]
+target = ".env"
+variants = { one = { source = "envs/one", default = true }, two = { source = "envs/two" } }
+hooks = { post_toggle = "LOG=hooks.log sh scripts/log.sh" }
+"#,
+    );
+    common::write(&ws, "envs/one", "ONE");
+    common::write(&ws, "envs/two", "TWO");
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("zsh");
+    cmd.assert().success();
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("disable").arg("zsh");
+    cmd.assert().success();
+    // the source of the variant switched to
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("toggle").arg("env").arg("two");
+    cmd.assert().success();
+
+    let ws_path = ws.display();
+    assert_eq!(
+        std::fs::read_to_string(ws.join("hooks.log"))?,
+        format!(
+            "enable zsh {ws}/dotfiles/zshrc {ws}/.zshrc {ws}/pono.toml {ws} \n\
+             disable zsh {ws}/dotfiles/zshrc {ws}/.zshrc {ws}/pono.toml {ws}/dotfiles zsh\n\
+             toggle env {ws}/envs/two {ws}/.env {ws}/pono.toml {ws} \n",
+            ws = ws_path
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn it_runs_structured_hooks() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("hook-structured");
+    common::write(&ws, "source", "source");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos.listed]
+source = "source"
+target = "listed"
+[ponos.listed.hooks]
+post_enable = [
+    "echo first >> hooks.log",
+    { run = ["sh", "-c", "echo \"$0\" >> hooks.log", "second argv"] },
+    { run = ["sh", "-c", "echo \"$0 $1\" >> hooks.log", "~/argv", "$PONO_NAME"] },
+    { run = "[[ -n $BASH_VERSION ]] && echo third bash >> hooks.log", shell = "bash" },
+    { run = "echo hidden output", quiet = true },
+]
+
+[ponos.slow]
+source = "source"
+target = "slow"
+hooks = { post_enable = { run = "sleep 5", timeout = "200ms" } }
+
+[ponos.daemon]
+source = "source"
+target = "daemon"
+hooks = { post_enable = "sleep 3 > /dev/null &" }
+
+[ponos.failing-daemon]
+source = "source"
+target = "failing-daemon"
+hooks = { post_enable = "sleep 3 > /dev/null & echo daemon failed >&2; exit 3" }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .env("HOME", ws.join("home"))
+        .arg("enable")
+        .arg("listed");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("hidden output").count(0));
+    assert_eq!(
+        std::fs::read_to_string(ws.join("hooks.log"))?,
+        format!(
+            "first\nsecond argv\n{}/home/argv listed\nthird bash\n",
+            ws.display()
+        )
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("slow");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "Pono link failed reason: (hook-timeout) post-enable hook was killed after 200ms",
+        ))
+        .stdout(predicate::str::contains("(rolled back)"));
+    assert!(std::fs::symlink_metadata(ws.join("slow")).is_err());
+
+    // commands left in the background don't keep pono waiting
+    let started = std::time::Instant::now();
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("daemon");
+    cmd.assert().success();
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("failing-daemon");
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "(hook-failed) post-enable hook exited with code 3: daemon failed",
+    ));
+    assert!(started.elapsed() < std::time::Duration::from_secs(2));
+
+    common::write(
+        &ws,
+        "invalid.toml",
+        r#"
+[ponos.slow]
+source = "source"
+target = "slow"
+hooks = { post_enable = { run = "sleep 5", timeout = "soon" } }
+"#,
+    );
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("-c")
+        .arg("invalid.toml")
+        .arg("list");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("Reason: (config-error)"))
+        .stdout(predicate::str::contains(
+            "Invalid timeout `soon`, use e.g. 30s or 5m",
+        ));
+
+    // misspelled options aren't ignored
+    common::write(
+        &ws,
+        "invalid.toml",
+        r#"
+[ponos.slow]
+source = "source"
+target = "slow"
+hooks = { post_enable = [{ run = "sleep 5", timout = "1s" }] }
+"#,
+    );
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("-c")
+        .arg("invalid.toml")
+        .arg("list");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("unknown field `timout`"));
+
+    Ok(())
+}
+
+#[test]
+fn it_runs_global_hooks_once_per_command() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("hook-global");
+    common::write(&ws, "dotfiles/zshrc", "zsh");
+    common::write(&ws, "dotfiles/vimrc", "vim");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[hooks.enable]
+before_all = "echo \"stop $PONO_ACTION $PONO_NAMES\" >> hooks.log"
+after_all = "echo start >> hooks.log"
+
+[hooks.disable]
+before_all = "exit 3"
+
+[ponos.vim]
+source = "dotfiles/vimrc"
+target = ".vimrc"
+hooks = { post_enable = "echo vim >> hooks.log" }
+
+[ponos.zsh]
+source = "dotfiles/zshrc"
+target = ".zshrc"
+hooks = { post_enable = "echo zsh >> hooks.log" }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("--dry-run");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("*: run before-all hook"))
+        .stdout(predicate::str::contains("*: run after-all hook"));
+    assert!(!ws.join("hooks.log").exists());
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable");
+    cmd.assert().success();
+    assert_eq!(
+        std::fs::read_to_string(ws.join("hooks.log"))?,
+        "stop enable vim zsh\nvim\nzsh\nstart\n"
+    );
+
+    // nothing is unlinked when the hook before all the ponos fails
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("disable");
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "(hook-failed) before-all hook exited with code 3",
+    ));
+    assert!(std::fs::symlink_metadata(ws.join(".vimrc"))?.is_symlink());
+    assert!(std::fs::symlink_metadata(ws.join(".zshrc"))?.is_symlink());
+
+    Ok(())
+}
+
+#[test]
+fn it_enables_ponos_again_without_failing() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("idempotent-enable");
+    common::write(&ws, "dotfiles/zshrc", "zsh");
+    common::write(&ws, "dotfiles/vimrc", "vim");
+    common::write(&ws, "dotfiles/other", "other");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos.vim]
+source = "dotfiles/vimrc"
+target = ".vimrc"
+
+[ponos.zsh]
+source = "dotfiles/zshrc"
+target = ".zshrc"
+hooks = { post_enable = "echo zsh >> hooks.log" }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable");
+    cmd.assert().success();
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("zsh: .zshrc (already linked)"))
+        .stdout(predicate::str::contains("vim: .vimrc (already linked)"))
+        .stdout(predicate::str::contains("(new link)").count(0));
+    // nothing changed, so the hooks didn't run again
+    assert_eq!(std::fs::read_to_string(ws.join("hooks.log"))?, "zsh\n");
+
+    // a broken link is pointed back to the source
+    std::fs::remove_file(ws.join(".vimrc"))?;
+    std::os::unix::fs::symlink(ws.join("dotfiles/removed"), ws.join(".vimrc"))?;
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("vim");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("vim: .vimrc (fixed link)"));
+    assert_eq!(std::fs::read_to_string(ws.join(".vimrc"))?, "vim");
+
+    // a link to another file is reported
+    std::fs::remove_file(ws.join(".vimrc"))?;
+    std::os::unix::fs::symlink(ws.join("dotfiles/other"), ws.join(".vimrc"))?;
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("enable").arg("vim");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "Reason: (mismatch) Target '.vimrc' points to",
+        ))
+        .stdout(predicate::str::contains("--on-conflict backup"));
+    assert_eq!(std::fs::read_to_string(ws.join(".vimrc"))?, "other");
+
+    Ok(())
+}
+
+#[test]
+fn it_syncs_the_links_with_the_config() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("sync");
+    for name in [
+        "zshrc",
+        "vimrc",
+        "tmux.conf",
+        "gitconfig",
+        "inputrc",
+        "other",
+        "gone",
+    ] {
+        common::write(&ws, &format!("dotfiles/{}", name), name);
+    }
+    common::write(&ws, ".inputrc", "CURRENT");
+    let pono = || {
+        let mut cmd = common::pono();
+        cmd.current_dir(&ws).env("XDG_STATE_HOME", ws.join("state"));
+        cmd
+    };
+
+    // links made by ponos removed from the config later on
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+other = { source = "dotfiles/other", target = ".otherrc" }
+gone = { source = "dotfiles/gone", target = ".gonerc" }
+"#,
+    );
+    pono().arg("enable").assert().success();
+    std::fs::remove_file(ws.join("dotfiles/gone"))?;
+
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+zsh = { source = "dotfiles/zshrc", target = ".zshrc" }
+vim = { source = "dotfiles/vimrc", target = ".vimrc" }
+tmux = { source = "dotfiles/tmux.conf", target = ".tmux.conf" }
+git = { source = "dotfiles/gitconfig", target = ".gitconfig" }
+inputrc = { source = "dotfiles/inputrc", target = ".inputrc" }
+"#,
+    );
+    let link = |source: &str, target: &str| std::os::unix::fs::symlink(source, ws.join(target));
+    link("dotfiles/zshrc", ".zshrc")?;
+    link("dotfiles/removed", ".tmux.conf")?;
+    link("dotfiles/other", ".gitconfig")?;
+    // not made by pono
+    link("dotfiles/other", ".manualrc")?;
+
+    pono()
+        .arg("sync")
+        .arg("--dry-run")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("vim: create link"))
+        .stdout(predicate::str::contains("tmux: relink"))
+        .stdout(predicate::str::contains("git: relink"))
+        .stdout(predicate::str::contains("zsh:").count(0));
+    assert!(std::fs::symlink_metadata(ws.join(".vimrc")).is_err());
+
+    // the other ponos are synced even if one can't be
+    pono()
+        .arg("sync")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("inputrc: .inputrc (invalid)"))
+        .stdout(predicate::str::contains("vim: .vimrc (new link)"))
+        .stdout(predicate::str::contains("tmux: .tmux.conf (fixed link)"))
+        .stdout(predicate::str::contains("git: .gitconfig (fixed link)"))
+        .stdout(predicate::str::contains(
+            "Synced: 1 new links, 2 fixed, 0 pruned, 1 ponos already in sync",
+        ))
+        .stdout(predicate::str::contains(
+            "1 invalid ponos were left untouched",
+        ));
+    for (target, content) in [
+        (".vimrc", "vimrc"),
+        (".tmux.conf", "tmux.conf"),
+        (".gitconfig", "gitconfig"),
+    ] {
+        assert_eq!(std::fs::read_to_string(ws.join(target))?, content);
+    }
+    assert_eq!(std::fs::read_to_string(ws.join(".inputrc"))?, "CURRENT");
+
+    std::fs::remove_file(ws.join(".inputrc"))?;
+    pono()
+        .arg("sync")
+        .arg("--prune")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("other: "))
+        .stdout(predicate::str::contains("/.otherrc (pruned)"))
+        .stdout(predicate::str::contains("/.gonerc (pruned)"))
+        .stdout(predicate::str::contains(
+            "Synced: 1 new links, 0 fixed, 2 pruned, 4 ponos already in sync",
+        ));
+    assert!(std::fs::symlink_metadata(ws.join(".otherrc")).is_err());
+    assert!(std::fs::symlink_metadata(ws.join(".gonerc")).is_err());
+    assert!(std::fs::symlink_metadata(ws.join(".manualrc"))?.is_symlink());
+
+    Ok(())
+}
+
+#[test]
+fn it_records_the_links_in_the_state() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("state");
+    common::write(&ws, "dotfiles/zshrc", "zsh");
+    common::write(&ws, "dotfiles/vimrc", "vim");
+    common::write(&ws, ".vimrc", "CURRENT");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+zsh = { source = "dotfiles/zshrc", target = ".zshrc" }
+vim = { source = "dotfiles/vimrc", target = ".vimrc" }
+"#,
+    );
+    let pono = || {
+        let mut cmd = common::pono();
+        cmd.current_dir(&ws).env("XDG_STATE_HOME", ws.join("state"));
+        cmd
+    };
+    let state = || std::fs::read_to_string(ws.join("state/pono/state.toml")).unwrap();
+
+    pono().arg("enable").arg("zsh").assert().success();
+    pono().arg("toggle").arg("vim").assert().success();
+    let ws_path = ws.display();
+    let recorded = state();
+    assert!(recorded.contains(&format!("config = \"{}/pono.toml\"", ws_path)));
+    assert!(recorded.contains(&format!(
+        "pono = \"zsh\"\nsource = \"{ws}/dotfiles/zshrc\"\ntarget = \"{ws}/.zshrc\"",
+        ws = ws_path
+    )));
+    assert!(recorded.contains("[[backups]]"));
+    assert!(recorded.contains(&format!("target = \"{}/.vimrc\"\nbackup = ", ws_path)));
+
+    // restoring the backup removes both the link and the backup records
+    pono().arg("restore").arg("vim").assert().success();
+    let recorded = state();
+    assert!(!recorded.contains("pono = \"vim\""));
+    assert!(!recorded.contains("[[backups]]"));
+
+    // links of ponos removed from the config are reported
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+vim = { source = "dotfiles/vimrc", target = ".vimrc" }
+"#,
+    );
+    pono()
+        .arg("status")
+        .assert()
+        .stdout(predicate::str::contains("Removed from the config:"))
+        .stdout(predicate::str::contains(format!(
+            "zsh {ws}/.zshrc -> {ws}/dotfiles/zshrc (stale link)",
+            ws = ws_path
+        )))
+        .stdout(predicate::str::contains("pono sync --prune"));
+
+    // links recorded in the state are owned even once the source moved in
+    // the config, disabling removes the record
+    common::write(&ws, "dotfiles/zsh/zshrc", "zsh");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+zsh = { source = "dotfiles/zsh/zshrc", target = ".zshrc" }
+"#,
+    );
+    pono().arg("disable").assert().success();
+    assert!(std::fs::symlink_metadata(ws.join(".zshrc")).is_err());
+    assert!(!state().contains("[[links]]"));
+
+    // links pono didn't make are still left alone
+    std::os::unix::fs::symlink(ws.join("dotfiles/zshrc"), ws.join(".zshrc"))?;
+    pono()
+        .arg("disable")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("(link-mismatch)"));
+    assert!(std::fs::symlink_metadata(ws.join(".zshrc"))?.is_symlink());
+
+    Ok(())
+}
+
+#[test]
+fn it_keeps_a_history_of_changes_to_undo() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("history");
+    common::write(&ws, "dotfiles/zshrc", "zsh");
+    common::write(&ws, "dotfiles/vimrc", "vim");
+    common::write(&ws, ".vimrc", "CURRENT");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+zsh = { source = "dotfiles/zshrc", target = ".zshrc" }
+vim = { source = "dotfiles/vimrc", target = ".vimrc" }
+"#,
+    );
+    let pono = || {
+        let mut cmd = common::pono();
+        cmd.current_dir(&ws).env("XDG_STATE_HOME", ws.join("state"));
+        cmd
+    };
+
+    pono()
+        .arg("undo")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing to undo"));
+
+    pono().arg("enable").arg("zsh").assert().success();
+    pono().arg("toggle").arg("vim").assert().success();
+    let ws_path = ws.display();
+    pono()
+        .arg("history")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(
+            r"  1 \d{4}-\d\d-\d\d \d\d:\d\d:\d\d pono enable zsh\n",
+        )?)
+        .stdout(predicate::str::contains(format!(
+            "      zsh: create link {ws}/.zshrc -> {ws}/dotfiles/zshrc",
+            ws = ws_path
+        )))
+        .stdout(predicate::str::contains("pono toggle vim"))
+        .stdout(predicate::str::contains(format!(
+            "      vim: rename {}/.vimrc -> {}/.vimrc.pono-",
+            ws_path, ws_path
+        )));
+
+    pono()
+        .arg("undo")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Undoing 2"))
+        .stdout(predicate::str::contains(format!(
+            "vim: remove link {}/.vimrc (undone)",
+            ws_path
+        )));
+    assert_eq!(std::fs::read_to_string(ws.join(".vimrc"))?, "CURRENT");
+    assert!(std::fs::symlink_metadata(ws.join(".zshrc"))?.is_symlink());
+
+    // undone invocations are skipped
+    pono().arg("undo").assert().success();
+    assert!(std::fs::symlink_metadata(ws.join(".zshrc")).is_err());
+    pono()
+        .arg("history")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("pono enable zsh (undone)"))
+        .stdout(predicate::str::contains("pono undo (undoes 2)"))
+        .stdout(predicate::str::contains("pono undo (undoes 1)"));
+    pono()
+        .arg("undo")
+        .arg("5")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing to undo"));
+
+    // what a failed invocation couldn't roll back is still recorded
+    common::write(&ws, "dotfiles/nvim/init.lua", "-- init");
+    std::fs::create_dir_all(ws.join("home"))?;
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos.nvim]
+source = "dotfiles/nvim"
+target = "home/nvim"
+mode = "tree"
+hooks = { post_enable = "touch home/nvim/extra; exit 1" }
+"#,
+    );
+    pono()
+        .arg("enable")
+        .arg("nvim")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("(rollback failed)"));
+    pono()
+        .arg("history")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "pono enable nvim (failed, not rolled back)",
+        ))
+        .stdout(predicate::str::contains(format!(
+            "      nvim: create directory {}/home/nvim",
+            ws_path
+        )));
+
+    Ok(())
+}
+
+#[test]
+fn it_links_relative_to_the_target_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("relative");
+    let moved = common::workspace("relative-moved");
+    std::fs::remove_dir(&moved)?;
+    common::write(&ws, "dotfiles/zshrc", "zsh");
+    common::write(&ws, "dotfiles/nvim/init.lua", "nvim");
+    common::write(&ws, "dotfiles/vimrc", "vim");
+    std::fs::create_dir_all(ws.join("home/.config"))?;
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+relative = true
+
+[ponos]
+zsh = { source = "dotfiles/zshrc", target = ".zshrc" }
+nvim = { source = "dotfiles/nvim", target = "home/.config/nvim" }
+vim = { source = "dotfiles/vimrc", target = ".vimrc", relative = false }
+"#,
+    );
+    let pono = |dir: &std::path::Path| {
+        let mut cmd = common::pono();
+        cmd.current_dir(dir)
+            .env("XDG_STATE_HOME", dir.join("state"));
+        cmd
+    };
+
+    pono(&ws).arg("enable").assert().success();
+    assert_eq!(
+        std::fs::read_link(ws.join(".zshrc"))?,
+        std::path::Path::new("dotfiles/zshrc")
+    );
+    assert_eq!(
+        std::fs::read_link(ws.join("home/.config/nvim"))?,
+        std::path::Path::new("../../dotfiles/nvim")
+    );
+    assert_eq!(
+        std::fs::read_link(ws.join(".vimrc"))?,
+        ws.join("dotfiles/vimrc")
+    );
+    pono(&ws)
+        .arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("zsh .zshrc (linked)"))
+        .stdout(predicate::str::contains("nvim home/.config/nvim (linked)"));
+    pono(&ws)
+        .arg("sync")
+        .arg("--prune")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 ponos already in sync"));
+
+    // relative links still work once the dotfiles are moved
+    std::fs::rename(&ws, &moved)?;
+    pono(&moved)
+        .arg("status")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("zsh .zshrc (linked)"))
+        .stdout(predicate::str::contains("nvim home/.config/nvim (linked)"))
+        .stdout(predicate::str::contains("vim .vimrc (broken)"));
+
+    pono(&moved)
+        .arg("disable")
+        .arg("zsh")
+        .arg("nvim")
+        .assert()
+        .success();
+    assert!(std::fs::symlink_metadata(moved.join(".zshrc")).is_err());
+    assert!(std::fs::symlink_metadata(moved.join("home/.config/nvim")).is_err());
+
+    Ok(())
+}
@@ -1,15 +1,13 @@
-use assert_cmd::Command;
 use predicates::prelude::predicate;
 
-const BINARY_NAME: &str = "pono";
+#[path = "./common/lib.rs"]
+mod common;
 
 fn cleanup() {
     let current_dir = std::env::current_dir().unwrap();
     let examples_dir = current_dir.join("examples/to");
-    let paths = std::fs::read_dir(&examples_dir).expect(&format!(
-        "CLEANUP: Failed to read directory {:?}",
-        examples_dir
-    ));
+    let paths = std::fs::read_dir(&examples_dir)
+        .unwrap_or_else(|_| panic!("CLEANUP: Failed to read directory {:?}", examples_dir));
 
     for path in paths.into_iter() {
         let path = path.unwrap().path();
@@ -17,13 +15,13 @@ fn cleanup() {
         if path.ends_with(".gitkeep") {
             continue;
         }
-        std::fs::remove_file(&path).expect(&format!("Failed to remove file {:?}", path));
+        std::fs::remove_file(&path).unwrap_or_else(|_| panic!("Failed to remove file {:?}", path));
     }
 }
 
 #[test]
 fn it_list_the_ponos_declared_in_the_config() -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
 
     cmd.arg("-c").arg("examples/basic.toml").arg("list");
 
@@ -39,7 +37,7 @@ fn it_list_the_ponos_declared_in_the_config() -> Result<(), Box<dyn std::error::
 #[test]
 fn it_link_the_ponos() -> Result<(), Box<dyn std::error::Error>> {
     cleanup();
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
 
     cmd.arg("-c").arg("examples/basic.toml").arg("enable");
 
@@ -64,7 +62,7 @@ fn it_link_the_ponos() -> Result<(), Box<dyn std::error::Error>> {
     let zsh_source_content = std::fs::read_to_string("examples/from/zshrc")?;
     assert_eq!(zsh_target_content, zsh_source_content);
 
-    cmd = Command::cargo_bin(BINARY_NAME)?;
+    cmd = common::pono();
     cmd.arg("-c")
         .arg("examples/basic.toml")
         .arg("status")
@@ -75,7 +73,7 @@ fn it_link_the_ponos() -> Result<(), Box<dyn std::error::Error>> {
         .stdout(predicate::str::contains("nvim ./examples/to/nvim (linked)"))
         .stdout(predicate::str::contains("zsh ./examples/to/.zshrc (linked)").count(0));
 
-    cmd = Command::cargo_bin(BINARY_NAME)?;
+    cmd = common::pono();
     cmd.arg("-c")
         .arg("examples/basic.toml")
         .arg("disable")
@@ -88,3 +86,300 @@ fn it_link_the_ponos() -> Result<(), Box<dyn std::error::Error>> {
     cleanup();
     Ok(())
 }
+
+#[test]
+fn it_prints_the_status_as_json_and_tsv() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("status-format");
+    common::write(&ws, "dotfiles/zshrc", "zsh");
+    common::write(&ws, "dotfiles/bashrc", "bash");
+    common::write(&ws, "dotfiles/gitconfig", "[user]");
+    common::write(&ws, ".gitconfig", "not a link");
+    std::os::unix::fs::symlink(ws.join("dotfiles/zshrc"), ws.join(".zshrc"))?;
+    std::os::unix::fs::symlink(ws.join("dotfiles/removed"), ws.join(".vimrc"))?;
+    common::write(&ws, "dotfiles/term-dark", "dark");
+    common::write(&ws, "dotfiles/term-light", "light");
+    std::os::unix::fs::symlink(ws.join("dotfiles/term-light"), ws.join(".termrc"))?;
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+zsh = { source = "dotfiles/zshrc", target = ".zshrc" }
+bash = { source = "dotfiles/bashrc", target = ".bashrc" }
+git = { source = "dotfiles/gitconfig", target = ".gitconfig" }
+vim = { source = "dotfiles/zshrc", target = ".vimrc" }
+
+[ponos.term]
+target = ".termrc"
+
+[ponos.term.variants]
+dark = { source = "dotfiles/term-dark", default = true }
+light = { source = "dotfiles/term-light" }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("status")
+        .arg("--format")
+        .arg("json");
+    let output = cmd.assert().failure().get_output().stdout.clone();
+    let json: serde_json::Value = serde_json::from_slice(&output)?;
+    let state = |name: &str| {
+        json.as_array()
+            .unwrap()
+            .iter()
+            .find(|pono| pono["name"] == name)
+            .map(|pono| pono["state"].as_str().unwrap().to_string())
+            .unwrap()
+    };
+    assert_eq!(state("zsh"), "linked");
+    assert_eq!(state("bash"), "missing");
+    assert_eq!(state("git"), "not-symlink");
+    assert_eq!(state("vim"), "dangling");
+    assert_eq!(state("term"), "linked");
+    let term = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|pono| pono["name"] == "term")
+        .unwrap();
+    assert_eq!(term["variant"], "light");
+    assert_eq!(
+        term["source"],
+        ws.join("dotfiles/term-light").to_string_lossy().to_string()
+    );
+    assert_eq!(json[0]["name"], "bash");
+    assert_eq!(json[0]["error_kind"], "not-found");
+    assert_eq!(
+        json[0]["target"],
+        ws.join(".bashrc").to_string_lossy().to_string()
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("status")
+        .arg("zsh")
+        .arg("--format=tsv");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "name\tsource\ttarget\tstate\tvariant\terror_kind\terror\n",
+        ))
+        .stdout(predicate::str::contains("\t.zshrc").count(0))
+        .stdout(predicate::str::contains("/.zshrc\tlinked\t\t\t\n"));
+
+    Ok(())
+}
+
+#[test]
+fn it_checks_links_by_file_identity() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("link-identity");
+    // same size, different files
+    common::write(&ws, "dotfiles/zshrc", "zsh");
+    common::write(&ws, "dotfiles/other", "sh!");
+    std::os::unix::fs::symlink(ws.join("dotfiles/zshrc"), ws.join(".zshrc"))?;
+    std::os::unix::fs::symlink(ws.join("dotfiles/other"), ws.join(".zshenv"))?;
+    std::os::unix::fs::symlink(ws.join("dotfiles/removed"), ws.join(".zprofile"))?;
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+zsh = { source = "dotfiles/zshrc", target = ".zshrc" }
+zshenv = { source = "dotfiles/zshrc", target = ".zshenv" }
+zprofile = { source = "dotfiles/zshrc", target = ".zprofile" }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("status");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("zsh .zshrc (linked)"))
+        .stdout(predicate::str::contains("zshenv .zshenv (broken)"))
+        .stdout(predicate::str::contains(format!(
+            "Reason: (link-mismatch) Target '.zshenv' points to '{}' instead of source 'dotfiles/zshrc'",
+            ws.join("dotfiles/other").display()
+        )))
+        .stdout(predicate::str::contains("zprofile .zprofile (broken)"))
+        .stdout(predicate::str::contains(format!(
+            "Reason: (dangling) Target '.zprofile' points to '{}' which does not exist",
+            ws.join("dotfiles/removed").display()
+        )));
+
+    Ok(())
+}
+
+#[test]
+fn it_resolves_relative_links_from_the_link_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("link-relative");
+    common::write(&ws, "dotfiles/zshrc", "zsh");
+    common::write(&ws, "home/.config/.keep", "");
+    std::os::unix::fs::symlink("../dotfiles/zshrc", ws.join("home/.zshrc"))?;
+    // only valid when resolved from the workspace, where pono runs
+    std::os::unix::fs::symlink("dotfiles/zshrc", ws.join("home/.zshenv"))?;
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"
+[ponos]
+zsh = { source = "dotfiles/zshrc", target = "home/.zshrc" }
+zshenv = { source = "dotfiles/zshrc", target = "home/.zshenv" }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("status");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("zsh home/.zshrc (linked)"))
+        .stdout(predicate::str::contains("zshenv home/.zshenv (broken)"))
+        .stdout(predicate::str::contains(
+            "Reason: (dangling) Target 'home/.zshenv' points to 'dotfiles/zshrc' which does not exist",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn it_writes_a_starter_config_with_init() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("init");
+    common::write(&ws, "home/.gitconfig", "[user]");
+    common::write(&ws, "home/.config/kitty/kitty.conf", "font_size 12");
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .env("HOME", ws.join("home"))
+        .arg("init")
+        .arg("--template=git-hooks");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Created pono.toml from the git-hooks template",
+        ))
+        .stdout(predicate::str::contains(
+            "Found dotfiles in $HOME: git, kitty.",
+        ));
+    let config = std::fs::read_to_string(ws.join("pono.toml"))?;
+    assert!(config.contains("# pre-commit = { source = \"./githooks/pre-commit\""));
+    assert!(config.contains("# git = { source = \"./gitconfig\", target = \"~/.gitconfig\" }"));
+    assert!(config.contains("# kitty = { source = \"./kitty\", target = \"~/.config/kitty\" }"));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("list");
+    cmd.assert().success();
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("init");
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "Config file pono.toml already exists",
+    ));
+    assert_eq!(std::fs::read_to_string(ws.join("pono.toml"))?, config);
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .env("HOME", ws.join("home"))
+        .arg("init")
+        .arg("--template=env-toggle")
+        .arg("--force");
+    cmd.assert().success();
+    let config = std::fs::read_to_string(ws.join("pono.toml"))?;
+    assert!(config.contains("# [ponos.env.variants]"));
+
+    // ponos of the template aren't offered twice
+    common::write(&ws, "home/.config/nvim/init.lua", "-- init");
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .env("HOME", ws.join("home"))
+        .arg("init")
+        .arg("--force");
+    cmd.assert().success().stdout(predicate::str::contains(
+        "Found dotfiles in $HOME: git, nvim, kitty.",
+    ));
+    let config = std::fs::read_to_string(ws.join("pono.toml"))?;
+    assert!(config.contains("# [ponos.nvim]"));
+    assert!(!config.contains("# nvim = "));
+    assert!(config.contains("# kitty = "));
+
+    // the config path is expanded like any other path
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .env("HOME", ws.join("home"))
+        .arg("-c")
+        .arg("~/pono.toml")
+        .arg("init");
+    cmd.assert().success().stdout(predicate::str::contains(
+        "Created ~/pono.toml from the dotfiles template",
+    ));
+    assert!(ws.join("home/pono.toml").exists());
+    assert!(!ws.join("~").exists());
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .env("HOME", ws.join("home"))
+        .arg("-c")
+        .arg("~/pono.toml")
+        .arg("init");
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "Config file ~/pono.toml already exists",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn it_scans_a_directory_into_ponos() -> Result<(), Box<dyn std::error::Error>> {
+    let ws = common::workspace("scan");
+    common::write(&ws, "stow/.zshrc", "zsh");
+    common::write(&ws, "stow/nvim/init.lua", "-- init");
+    common::write(&ws, "stow/.git/HEAD", "");
+    common::write(
+        &ws,
+        "pono.toml",
+        r#"# my ponos
+[ponos]
+nvim = { source = "./nvim-config", target = "~/.config/nvim" }
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("scan")
+        .arg("./stow")
+        .arg("--target-root=~/.config");
+    cmd.assert().success().stdout(
+        r#"[ponos.nvim]
+source = "./stow/nvim"
+target = "~/.config/nvim"
+
+[ponos.zshrc]
+source = "./stow/.zshrc"
+target = "~/.config/.zshrc"
+"#,
+    );
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws)
+        .arg("scan")
+        .arg("./stow")
+        .arg("--write");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "nvim: ./stow/nvim (skipped, already in config)",
+        ))
+        .stdout(predicate::str::contains("zshrc: added to pono.toml"));
+
+    let config = std::fs::read_to_string(ws.join("pono.toml"))?;
+    assert!(config.starts_with("# my ponos\n[ponos]\nnvim = "));
+    assert!(config.ends_with("[ponos.zshrc]\nsource = \"./stow/.zshrc\"\ntarget = \"~/.zshrc\"\n"));
+
+    let mut cmd = common::pono();
+    cmd.current_dir(&ws).arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("zshrc: ./stow/.zshrc"));
+
+    Ok(())
+}
@@ -2,10 +2,8 @@
 pub fn cleanup() {
     let current_dir = std::env::current_dir().unwrap();
     let examples_dir = current_dir.join("examples/to");
-    let paths = std::fs::read_dir(&examples_dir).expect(&format!(
-        "CLEANUP: Failed to read directory {:?}",
-        examples_dir
-    ));
+    let paths = std::fs::read_dir(&examples_dir)
+        .unwrap_or_else(|_| panic!("CLEANUP: Failed to read directory {:?}", examples_dir));
 
     for path in paths.into_iter() {
         let path = path.unwrap().path();
@@ -13,6 +11,33 @@ pub fn cleanup() {
         if path.ends_with(".gitkeep") {
             continue;
         }
-        std::fs::remove_file(&path).expect(&format!("Failed to remove file {:?}", path));
+        std::fs::remove_file(&path).unwrap_or_else(|_| panic!("Failed to remove file {:?}", path));
     }
 }
+
+/// The pono binary, keeping its state and history under cargo's temporary
+/// directory instead of the ones of the machine running the tests
+pub fn pono() -> assert_cmd::Command {
+    let mut cmd = assert_cmd::Command::cargo_bin("pono").unwrap();
+    cmd.env(
+        "XDG_STATE_HOME",
+        std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"),
+    );
+    cmd
+}
+
+/// Creates an empty directory for a test under cargo's temporary directory.
+/// Tests that need their own files use it so they don't race with `cleanup`.
+pub fn workspace(name: &str) -> std::path::PathBuf {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a file relative to `dir`, creating its parent directories
+pub fn write(dir: &std::path::Path, relative: &str, content: &str) {
+    let path = dir.join(relative);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
@@ -1,11 +1,11 @@
-use assert_cmd::Command;
 use predicates::prelude::predicate;
 
-const BINARY_NAME: &str = "pono";
+#[path = "./common/lib.rs"]
+mod common;
 
 #[test]
 fn it_fails_when_config_file_doesnt_exist() -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
 
     cmd.arg("-c")
         .arg("examples/configs/unknown.toml")
@@ -27,7 +27,7 @@ fn it_fails_when_config_file_doesnt_exist() -> Result<(), Box<dyn std::error::Er
 #[test]
 fn it_fails_when_config_lacks_packages() -> Result<(), Box<dyn std::error::Error>> {
     let pono_config = "examples/configs/invalid-missing-packages.toml";
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
 
     cmd.arg("-c").arg(pono_config).arg("list");
 
@@ -47,7 +47,7 @@ fn it_fails_when_config_lacks_packages() -> Result<(), Box<dyn std::error::Error
 #[test]
 fn it_fails_when_contains_invalid_package() -> Result<(), Box<dyn std::error::Error>> {
     let pono_config = "examples/configs/invalid-package.toml";
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
 
     cmd.arg("-c").arg(pono_config).arg("list");
 
@@ -67,7 +67,7 @@ fn it_fails_when_contains_invalid_package() -> Result<(), Box<dyn std::error::Er
 #[test]
 fn it_fails_when_target_exist_and_isnt_a_symbolic_link() -> Result<(), Box<dyn std::error::Error>> {
     let pono_config = "examples/configs/invalid-target-is-not-link.toml";
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
 
     cmd.arg("-c").arg(pono_config).arg("enable").arg("notlink");
 
@@ -84,7 +84,7 @@ fn it_fails_when_target_exist_and_isnt_a_symbolic_link() -> Result<(), Box<dyn s
 #[test]
 fn it_fails_when_source_is_missing() -> Result<(), Box<dyn std::error::Error>> {
     let pono_config = "examples/configs/invalid-target-is-not-link.toml";
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
 
     cmd.arg("-c")
         .arg(pono_config)
@@ -103,7 +103,7 @@ fn it_fails_when_source_is_missing() -> Result<(), Box<dyn std::error::Error>> {
 fn it_does_not_fail_when_missing_config_toml_for_completion(
 ) -> Result<(), Box<dyn std::error::Error>> {
     // change the current directory to the root of the project
-    let mut cmd = Command::cargo_bin(BINARY_NAME)?;
+    let mut cmd = common::pono();
 
     cmd.arg("-c")
         .arg("examples/configs/unknown.toml")
@@ -114,3 +114,111 @@ fn it_does_not_fail_when_missing_config_toml_for_completion(
 
     Ok(())
 }
+
+#[test]
+fn it_rolls_back_links_when_enable_fails_midway() -> Result<(), Box<dyn std::error::Error>> {
+    let pono_config = "examples/configs/partial-failure.toml";
+    let mut cmd = common::pono();
+
+    cmd.arg("-c").arg(pono_config).arg("enable");
+
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "a:valid: examples/to/.rollback-zshrc (new link)",
+        ))
+        .stdout(predicate::str::contains("Pono link failed reason"))
+        .stdout(predicate::str::contains("b:broken: create link"))
+        .stdout(predicate::str::contains("examples/to/.rollback-zshrc -> "))
+        .stdout(predicate::str::contains(
+            "examples/from/zshrc (rolled back)",
+        ));
+
+    assert!(std::fs::symlink_metadata("examples/to/.rollback-zshrc").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn it_reports_errors_and_plan_without_linking_on_dry_run() -> Result<(), Box<dyn std::error::Error>>
+{
+    let pono_config = "examples/configs/invalid-target-is-not-link.toml";
+    let mut cmd = common::pono();
+
+    cmd.arg("-c")
+        .arg(pono_config)
+        .arg("--dry-run")
+        .arg("enable");
+
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("Invalid pono: doesnexist"))
+        .stdout(predicate::str::contains("Dry run (nothing was changed):"))
+        .stdout(predicate::str::contains("nvim: create link"))
+        .stdout(predicate::str::contains(
+            "Dry run found invalid ponos, a real run would fail",
+        ));
+
+    assert!(std::fs::symlink_metadata("examples/to/nvim").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn it_does_not_run_hooks_on_dry_run() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = common::pono();
+
+    cmd.arg("-c")
+        .arg("examples/basic.toml")
+        .arg("enable")
+        .arg("with-hooks")
+        .arg("--dry-run");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "with-hooks: run pre-enable hook: echo 'Running pre_enable hook for with-hooks'",
+        ))
+        .stdout(predicate::str::is_match("(?m)^Running pre_enable hook")?.count(0));
+
+    assert!(std::fs::symlink_metadata("examples/to/.hooks").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn it_fails_when_variants_have_more_than_one_default() -> Result<(), Box<dyn std::error::Error>> {
+    let pono_config = "examples/configs/invalid-variants.toml";
+    let mut cmd = common::pono();
+
+    cmd.arg("-c").arg(pono_config).arg("list");
+
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "Reason: (config-error) Pono alacritty must have exactly one default variant",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn it_fails_when_selected_pono_is_unknown() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = common::pono();
+    cmd.arg("-c")
+        .arg("examples/basic.toml")
+        .arg("disable")
+        .arg("foo");
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "Reason: (not-found) Pono not found foo in ponos list",
+    ));
+
+    let mut cmd = common::pono();
+    cmd.arg("-c")
+        .arg("examples/basic.toml")
+        .arg("status")
+        .arg("git:*");
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "Reason: (not-found) No ponos match the pattern git:*",
+    ));
+
+    Ok(())
+}